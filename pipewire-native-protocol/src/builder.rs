@@ -0,0 +1,246 @@
+// Configuration of a connection: the client properties, protocol version, channel sizes and remote
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{io, net::UnixStream};
+
+use crate::{
+    client::ClientProxy,
+    core_proxy::CoreProxy,
+    reconnect::{Reconnect, ReconnectPolicy},
+    record::{Recorder, SharedRecorder},
+    remote,
+    tap::{MessageTap, SharedTap},
+    PipewireConnection,
+};
+
+// Version of the core interface sent in the Hello message
+pub const DEFAULT_PROTOCOL_VERSION: i32 = 3;
+
+// Property keys filled in by the builder
+pub const KEY_APPLICATION_NAME: &str = "application.name";
+pub const KEY_APPLICATION_ID: &str = "application.id";
+pub const KEY_APPLICATION_PROCESS_ID: &str = "application.process.id";
+pub const KEY_APPLICATION_PROCESS_BINARY: &str = "application.process.binary";
+pub const KEY_MEDIA_CATEGORY: &str = "media.category";
+
+// Number of events each proxy channel holds. Events for a proxy that does not keep up wait behind
+// its channel in order, the connection never waits for a proxy to read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelCapacities {
+    pub core: usize,
+    pub client: usize,
+    pub registry: usize,
+    pub device: usize,
+    pub factory: usize,
+    pub link: usize,
+    pub module: usize,
+    pub node: usize,
+    pub port: usize,
+    pub client_node: usize,
+    pub metadata: usize,
+    pub profiler: usize,
+    pub security_context: usize,
+}
+
+impl Default for ChannelCapacities {
+    fn default() -> Self {
+        Self {
+            core: 8,
+            client: 8,
+            registry: 100,
+            device: 100,
+            factory: 100,
+            link: 100,
+            module: 100,
+            node: 100,
+            port: 100,
+            client_node: 100,
+            metadata: 100,
+            profiler: 100,
+            security_context: 8,
+        }
+    }
+}
+
+impl ChannelCapacities {
+    // Tokio channels can't be empty, a capacity of 0 is raised to 1
+    fn clamped(self) -> Self {
+        Self {
+            core: self.core.max(1),
+            client: self.client.max(1),
+            registry: self.registry.max(1),
+            device: self.device.max(1),
+            factory: self.factory.max(1),
+            link: self.link.max(1),
+            module: self.module.max(1),
+            node: self.node.max(1),
+            port: self.port.max(1),
+            client_node: self.client_node.max(1),
+            metadata: self.metadata.max(1),
+            profiler: self.profiler.max(1),
+            security_context: self.security_context.max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectionBuilder {
+    properties: HashMap<String, String>,
+    version: i32,
+    capacities: ChannelCapacities,
+    remote: Option<String>,
+    reconnect: Option<ReconnectPolicy>,
+    tap: Option<SharedTap>,
+    recorder: Option<SharedRecorder>,
+}
+
+impl Default for ConnectionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnectionBuilder {
+    pub fn new() -> Self {
+        Self {
+            properties: HashMap::new(),
+            version: DEFAULT_PROTOCOL_VERSION,
+            capacities: ChannelCapacities::default(),
+            remote: None,
+            reconnect: None,
+            tap: None,
+            recorder: None,
+        }
+    }
+
+    // Set a client property, overriding the ones filled in automatically
+    pub fn property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn properties(mut self, properties: HashMap<String, String>) -> Self {
+        self.properties.extend(properties);
+        self
+    }
+
+    pub fn application_name(self, name: impl Into<String>) -> Self {
+        self.property(KEY_APPLICATION_NAME, name)
+    }
+
+    pub fn application_id(self, id: impl Into<String>) -> Self {
+        self.property(KEY_APPLICATION_ID, id)
+    }
+
+    pub fn media_category(self, category: impl Into<String>) -> Self {
+        self.property(KEY_MEDIA_CATEGORY, category)
+    }
+
+    // The core interface version sent in the Hello message
+    pub fn version(mut self, version: i32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn channel_capacities(mut self, capacities: ChannelCapacities) -> Self {
+        self.capacities = capacities.clamped();
+        self
+    }
+
+    // The remote to connect to, see PipewireConnection::connect_remote for the format.
+    // Without one PIPEWIRE_REMOTE is used, or pipewire-0. Clients with a remote.intention
+    // property of "manager" try the manager socket before pipewire-0
+    pub fn remote(mut self, remote: impl Into<String>) -> Self {
+        self.remote = Some(remote.into());
+        self
+    }
+
+    // Reestablish the connection when it is lost, proxies get ConnectionLost and Reconnected events.
+    // Only used with connect, a stream passed to connect_stream can not be opened again
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    // Write every message sent and received to output, decoded like the mod.protocol debug log
+    // of libpipewire. Errors writing to output are ignored. Every message is written and flushed
+    // synchronously by the task that sends or receives it, so an output that blocks, like a pipe
+    // nobody reads, holds up the whole connection
+    pub fn message_tap(mut self, output: impl std::io::Write + Send + 'static) -> Self {
+        self.tap = Some(Arc::new(Mutex::new(MessageTap::new(output))));
+        self
+    }
+
+    // Record every message sent and received to output, see record::Recording for reading it back.
+    // Errors writing to output are ignored
+    pub fn record(mut self, output: impl std::io::Write + Send + 'static) -> Self {
+        self.recorder = Some(Arc::new(Mutex::new(Recorder::new(output))));
+        self
+    }
+
+    // Resolve the remote and perform the Hello/UpdateProperties handshake on it
+    pub async fn connect(self) -> io::Result<(CoreProxy, ClientProxy)> {
+        let properties = self.client_properties();
+        let remote = match &self.remote {
+            Some(remote) => remote.clone(),
+            None => remote::default_remote(&properties),
+        };
+        let stream = remote::connect(&remote).await?;
+        let reconnect = self.reconnect.map(|policy| Reconnect {
+            policy,
+            remote,
+            version: self.version,
+            properties: properties.clone(),
+        });
+        PipewireConnection::start(
+            stream,
+            self.capacities,
+            self.version,
+            properties,
+            reconnect,
+            self.tap,
+            self.recorder,
+        )
+        .await
+    }
+
+    // Perform the handshake on an already connected socket, the remote is ignored
+    pub async fn connect_stream(self, stream: UnixStream) -> io::Result<(CoreProxy, ClientProxy)> {
+        let properties = self.client_properties();
+        PipewireConnection::start(
+            stream,
+            self.capacities,
+            self.version,
+            properties,
+            None,
+            self.tap,
+            self.recorder,
+        )
+        .await
+    }
+
+    fn client_properties(&self) -> HashMap<String, String> {
+        let binary = env::current_exe()
+            .ok()
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()));
+        let mut properties = HashMap::from([
+            (
+                KEY_APPLICATION_NAME.to_string(),
+                binary.clone().unwrap_or_else(|| "pipewirers".to_string()),
+            ),
+            (
+                KEY_APPLICATION_PROCESS_ID.to_string(),
+                std::process::id().to_string(),
+            ),
+        ]);
+        if let Some(binary) = binary {
+            properties.insert(KEY_APPLICATION_PROCESS_BINARY.to_string(), binary);
+        }
+        properties.extend(self.properties.clone());
+        properties
+    }
+}
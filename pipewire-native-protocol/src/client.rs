@@ -1,51 +1,123 @@
-use std::{ collections::HashMap, ops::{Deref, DerefMut}, sync::Arc};
+use std::{ collections::HashMap, sync::Arc};
 
 use spa::{
-    deserialize::{DeserializeError, PodDeserializer}, opcode::{self, MessageOpCode}, serialize::PodSerializer
+    deserialize::{
+        DeserializeError, DeserializeSuccess, PodDeserialize, PodDeserializer,
+        StructPodDeserializer, Visitor,
+    },
+    opcode::{self, MessageOpCode},
+    serialize::{GenError, PodSerialize, PodSerializer, SerializeSuccess},
 };
 use spa_derive::{ PodDeserialize, PodSerialize};
 use tokio::{io, sync::Mutex};
 
-use crate::{core_proxy, PipewireWriter};
-
+use crate::{
+    permissions::{self, PermissionList},
+    proxy, PipewireWriter, Proxies,
+};
 
-// Proxy
-pub struct ClientProxy {
-    connection: Arc<Mutex<PipewireWriter>>,
-    event_receiver: tokio::sync::mpsc::Receiver<ClientEvent>
+// Proxy for our own client, or for another client bound from its global in the registry
+proxy::define_proxy! {
+    pub struct ClientProxy: ClientEvent {}
+    bindable("PipeWire:Interface:Client", 3, client, client_proxies)
 }
 
 impl ClientProxy {
     pub const CLIENT_ID: i32 = 1;
 
-    pub(crate) async fn new(connection: Arc<Mutex<PipewireWriter>>, event_receiver: tokio::sync::mpsc::Receiver<ClientEvent>, properties: HashMap<String, String>) -> io::Result<ClientProxy>{
-        let mut  client = ClientProxy {connection, event_receiver };
+    pub(crate) async fn new(
+        connection: Arc<Mutex<PipewireWriter>>,
+        event_receiver: tokio::sync::mpsc::Receiver<ClientEvent>,
+        proxies: Arc<Mutex<Proxies>>,
+        properties: HashMap<String, String>,
+    ) -> io::Result<ClientProxy> {
+        let client =
+            ClientProxy::from_parts(ClientProxy::CLIENT_ID, connection, event_receiver, proxies);
         client.update_properties(properties).await?;
         Ok(client)
     }
 
     pub async fn update_properties(&self, properties: HashMap<String, String>) -> io::Result<()> {
-        self.connection.lock().await.call_method(
-            ClientProxy::CLIENT_ID,
-            2,
-            UpdateProperties {
-                props: properties,
-            },
-        )
-        .await
+        self.connection
+            .lock()
+            .await
+            .call_method(
+                self.id,
+                UpdateProperties::OP_CODE,
+                UpdateProperties { props: properties },
+            )
+            .await
     }
-}
 
-impl Deref for ClientProxy {
-    type Target = tokio::sync::mpsc::Receiver<ClientEvent>;
+    // Send an error to the client, about the object with id in that client
+    pub async fn error(&self, id: i32, res: i32, error: &str) -> io::Result<()> {
+        self.connection
+            .lock()
+            .await
+            .call_method(
+                self.id,
+                Error::OP_CODE,
+                Error {
+                    id,
+                    res,
+                    error: error.to_string(),
+                },
+            )
+            .await
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.event_receiver
+    // Get num permissions of this client starting at index, a num of 0 gets all of them.
+    // Collects Permissions events until the server is done, other events received meanwhile are kept for recv
+    pub async fn get_permissions(
+        &mut self,
+        index: i32,
+        num: i32,
+    ) -> io::Result<Vec<(i32, permissions::Permissions)>> {
+        let num = if num == 0 { i32::MAX } else { num };
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(
+                &self.proxies,
+                self.id,
+                GetPermissions::OP_CODE,
+                GetPermissions { index, num },
+            )
+            .await?;
+
+        let mut permissions = Vec::new();
+        self.receive_until(pending_sync, |event| match event {
+            ClientEvent::Permissions(event) => {
+                permissions.extend(event.permissions.0);
+                None
+            }
+            event => Some(event),
+        })
+        .await?;
+        Ok(permissions)
     }
-}
-impl DerefMut for ClientProxy {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.event_receiver
+
+    // Set the permissions of this client on the global ids, permissions::ID_ANY sets the default.
+    // Needs the M permission on the client object
+    pub async fn update_permissions(
+        &self,
+        permissions: &[(i32, permissions::Permissions)],
+    ) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(
+                &self.proxies,
+                self.id,
+                UpdatePermissions::OP_CODE,
+                UpdatePermissions {
+                    permissions: permissions.to_vec(),
+                },
+            )
+            .await?;
+        pending_sync.await
     }
 }
 
@@ -71,20 +143,59 @@ pub struct GetPermissions {
     pub num: i32,
 }
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
+// Sent flat as Struct(Int n_permissions, (Int id, Int permissions)*)
+#[derive(Debug)]
 #[spa_derive::opcode(4)]
 pub struct UpdatePermissions {
-    pub index: i32,
-    pub num: i32,
+    pub permissions: Vec<(i32, permissions::Permissions)>,
+}
+
+impl PodSerialize for UpdatePermissions {
+    fn serialize<O: std::io::Write + std::io::Seek>(
+        &self,
+        serializer: PodSerializer<O>,
+    ) -> Result<SerializeSuccess<O>, GenError> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field(&(self.permissions.len() as i32))?;
+        for (id, permissions) in &self.permissions {
+            serializer.serialize_field(id)?;
+            serializer.serialize_field(permissions)?;
+        }
+        serializer.end()
+    }
+}
+
+impl<'de> PodDeserialize<'de> for UpdatePermissions {
+    fn deserialize(
+        deserializer: PodDeserializer<'de>,
+    ) -> Result<(Self, DeserializeSuccess<'de>), DeserializeError<&'de [u8]>>
+    where
+        Self: Sized,
+    {
+        struct UpdatePermissionsVisitor;
+        impl<'de> Visitor<'de> for UpdatePermissionsVisitor {
+            type Value = UpdatePermissions;
+            type ArrayElem = std::convert::Infallible;
+
+            fn visit_struct(
+                &self,
+                struct_deserializer: &mut StructPodDeserializer<'de>,
+            ) -> Result<Self::Value, DeserializeError<&'de [u8]>> {
+                let permissions = permissions::deserialize_permissions(struct_deserializer)?;
+                Ok(UpdatePermissions { permissions })
+            }
+        }
+        deserializer.deserialize_struct(UpdatePermissionsVisitor)
+    }
 }
 
 // === Events ===
-#[derive(Debug)]
-pub enum ClientEvent {
-    Info(Info),
-    Permissions(Permissions),
-    // Added to allow for receiving Done events on all proxies
-    Done(core_proxy::Done)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum ClientEvent {
+        Info(Info),
+        Permissions(Permissions),
+    }
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
@@ -98,27 +209,27 @@ pub struct Info {
 #[derive(PodSerialize, PodDeserialize, Debug)]
 #[spa_derive::opcode(1)]
 pub struct Permissions {
-    pub index: i32,
-    pub permissions: HashMap<i32, i32>,
+    pub index: i32, // Of the first permission in the list
+    pub permissions: PermissionList,
 }
 
 impl opcode::DeserializeFromOpCode for ClientEvent {
 
-    fn deserialize_from_opcode<'de>(
+    fn deserialize_from_opcode(
         opcode: u32,
-        buffer: &'de [u8],
-    ) -> Result<(&'de [u8], Self), spa::deserialize::DeserializeError<&'de [u8]>>
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
     where
         Self: Sized,
     {
         match opcode {
             Info::OP_CODE => {
-                let (remain, value): (&[u8], Info) = PodDeserializer::deserialize_from(&buffer)?;
+                let (remain, value): (&[u8], Info) = PodDeserializer::deserialize_from(buffer)?;
                 Ok((remain, ClientEvent::Info(value)))
             }
             Permissions::OP_CODE => {
                 let (remain, value): (&[u8], Permissions) =
-                    PodDeserializer::deserialize_from(&buffer)?;
+                    PodDeserializer::deserialize_from(buffer)?;
                 Ok((remain, ClientEvent::Permissions(value)))
             }
             _ => Err(DeserializeError::InvalidType),
@@ -1,16 +1,24 @@
 use std::collections::HashMap;
 
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+
+use crate::{proxy, Fds};
 // === Methods ===
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(1)]
 pub struct GetNode {
     pub version: i32,
     pub new_id: i32,
 }
 
 #[derive(Debug)]
+#[opcode(2)]
 pub struct Update {
     pub change_mask: i32,
     pub params: Vec<spa::value::Value>, // This is implemented as a n_params, followed by a n spa values, why the hell is it that?
@@ -18,6 +26,7 @@ pub struct Update {
 }
 
 #[derive(Debug)]
+#[opcode(3)]
 pub struct PortUpdate {
     pub direction: i32,
     pub port_id: i32,
@@ -27,12 +36,13 @@ pub struct PortUpdate {
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(4)]
 pub struct SetActive {
     pub active: bool,
 }
-// Both a method and event
 #[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct Event {
+#[opcode(5)]
+pub struct EventMethod {
     pub event: spa::value::Value,
 }
 // DataPlane is the inner "struct" inside a buffer, but not a spa struct according to the spec, and also the Vec in Buffer and PortBuffer are not spa arrays according to spec
@@ -50,6 +60,7 @@ pub struct Buffer {
 }
 
 #[derive(Debug)]
+#[opcode(6)]
 pub struct PortBuffers {
     pub direction: i32,
     pub port_id: i32,
@@ -58,22 +69,27 @@ pub struct PortBuffers {
 }
 
 // === Events ===
-pub enum ClientNodeEvent {
-    Transport(Transport),
-    SetParam(SetParam),
-    SetIo(SetIO),
-    Event(Event),
-    Command(Command),
-    AddPort(AddPort),
-    RemovePort(RemovePort),
-    PortSetParam(PortSetParam),
-    UseBuffers(UseBuffers),
-    PortSetIo(PortSetIO),
-    SetActivation(SetActivation),
-    PortSetMixInfo(PortSetMixInfo),
+// Fd fields of Transport and SetActivation are indices into the fds that was sent along with the event
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum ClientNodeEvent {
+        Transport(Transport, Fds),
+        SetParam(SetParam),
+        SetIo(SetIO),
+        Event(Event),
+        Command(Command),
+        AddPort(AddPort),
+        RemovePort(RemovePort),
+        PortSetParam(PortSetParam),
+        UseBuffers(UseBuffers),
+        PortSetIo(PortSetIO),
+        SetActivation(SetActivation, Fds),
+        PortSetMixInfo(PortSetMixInfo),
+    }
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(0)]
 pub struct Transport {
     pub readfd: spa::value::Fd,
     pub write: spa::value::Fd,
@@ -83,13 +99,15 @@ pub struct Transport {
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(1)]
 pub struct SetParam {
-    pub id: i32,
+    pub id: spa::value::Id,
     pub flags: i32,
     pub param: spa::value::Value,
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(2)]
 pub struct SetIO {
     pub id: spa::value::Id,
     pub memid: i32,
@@ -97,12 +115,21 @@ pub struct SetIO {
     pub size: i32,
 }
 
+// The event variant of EventMethod
+#[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(3)]
+pub struct Event {
+    pub event: spa::value::Value,
+}
+
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(4)]
 pub struct Command {
     pub command: spa::value::Value,
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(5)]
 pub struct AddPort {
     pub direction: i32,
     pub port_id: i32,
@@ -110,12 +137,14 @@ pub struct AddPort {
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(6)]
 pub struct RemovePort {
     pub direction: i32,
     pub port_id: i32,
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(7)]
 pub struct PortSetParam {
     pub direction: i32,
     pub port_id: i32,
@@ -125,11 +154,13 @@ pub struct PortSetParam {
 }
 
 // Not a real spa struct
+#[derive(Debug)]
 pub struct Meta {
     pub type_: spa::value::Id,
     pub size: i32,
 }
 // Not a real spa struct
+#[derive(Debug)]
 pub struct DataBlock {
     pub type_: spa::value::Id,
     pub data: i32,
@@ -138,6 +169,7 @@ pub struct DataBlock {
     pub maxsize: i32,
 }
 // Not a real spa struct
+#[derive(Debug)]
 pub struct MixerBuffer {
     pub memid: i32,
     pub offset: i32,
@@ -146,7 +178,8 @@ pub struct MixerBuffer {
     pub metas: Vec<Meta>,            // Not a real spa array
     pub data_blocks: Vec<DataBlock>, // Not a real spa array
 }
-//TODO: Implement PodSerDe
+#[derive(Debug)]
+#[opcode(8)]
 pub struct UseBuffers {
     pub direction: i32,
     pub port_id: i32,
@@ -156,6 +189,7 @@ pub struct UseBuffers {
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(9)]
 pub struct PortSetIO {
     pub direction: i32,
     pub port_id: i32,
@@ -167,6 +201,7 @@ pub struct PortSetIO {
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(10)]
 pub struct SetActivation {
     pub nodeid: i32,
     pub signalfd: spa::value::Fd,
@@ -176,6 +211,7 @@ pub struct SetActivation {
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(11)]
 pub struct PortSetMixInfo {
     pub direction: i32,
     pub port_id: i32,
@@ -184,6 +220,79 @@ pub struct PortSetMixInfo {
     pub probs: HashMap<String, String>,
 }
 
+// The fds of Transport and SetActivation are attached by the reader after deserialization
+impl opcode::DeserializeFromOpCode for ClientNodeEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Transport::OP_CODE => {
+                let (remain, value): (&[u8], Transport) =
+                    PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::Transport(value, Fds::default())))
+            }
+            SetParam::OP_CODE => {
+                let (remain, value): (&[u8], SetParam) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::SetParam(value)))
+            }
+            SetIO::OP_CODE => {
+                let (remain, value): (&[u8], SetIO) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::SetIo(value)))
+            }
+            Event::OP_CODE => {
+                let (remain, value): (&[u8], Event) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::Event(value)))
+            }
+            Command::OP_CODE => {
+                let (remain, value): (&[u8], Command) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::Command(value)))
+            }
+            AddPort::OP_CODE => {
+                let (remain, value): (&[u8], AddPort) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::AddPort(value)))
+            }
+            RemovePort::OP_CODE => {
+                let (remain, value): (&[u8], RemovePort) =
+                    PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::RemovePort(value)))
+            }
+            PortSetParam::OP_CODE => {
+                let (remain, value): (&[u8], PortSetParam) =
+                    PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::PortSetParam(value)))
+            }
+            UseBuffers::OP_CODE => {
+                let (remain, value): (&[u8], UseBuffers) =
+                    PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::UseBuffers(value)))
+            }
+            PortSetIO::OP_CODE => {
+                let (remain, value): (&[u8], PortSetIO) =
+                    PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::PortSetIo(value)))
+            }
+            SetActivation::OP_CODE => {
+                let (remain, value): (&[u8], SetActivation) =
+                    PodDeserializer::deserialize_from(buffer)?;
+                Ok((
+                    remain,
+                    ClientNodeEvent::SetActivation(value, Fds::default()),
+                ))
+            }
+            PortSetMixInfo::OP_CODE => {
+                let (remain, value): (&[u8], PortSetMixInfo) =
+                    PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ClientNodeEvent::PortSetMixInfo(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
+}
+
 // PodSerialize/PodDeserialize
 // We have to do this manuel implementation of PodSerialize and PodDeserialize because params is stored as n_params followed by n spa pods instead of nice array
 impl spa::serialize::PodSerialize for Update {
@@ -222,20 +331,20 @@ impl<'de> spa::deserialize::PodDeserialize<'de> for Update {
             ) -> Result<Self::Value, spa::deserialize::DeserializeError<&'de [u8]>> {
                 let change_mask: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no change_mask field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let n_params: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no n_params field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let mut params: Vec<spa::value::Value> = Vec::new();
                 for _ in 0..n_params {
                     let param: spa::value::Value = struct_deserializer
                         .deserialize_field()?
-                        .expect("Input missing param");
+                        .ok_or(DeserializeError::PropertyMissing)?;
                     params.push(param);
                 }
                 let info: crate::node::Info = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no info field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 Ok(Update {
                     change_mask,
                     params,
@@ -285,26 +394,26 @@ impl<'de> spa::deserialize::PodDeserialize<'de> for PortUpdate {
             ) -> Result<Self::Value, spa::deserialize::DeserializeError<&'de [u8]>> {
                 let direction: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no change_mask field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let port_id: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no change_mask field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let change_mask: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no change_mask field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let n_params: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no n_params field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let mut params: Vec<spa::value::Value> = Vec::new();
                 for _ in 0..n_params {
                     let param: spa::value::Value = struct_deserializer
                         .deserialize_field()?
-                        .expect("Input missing param");
+                        .ok_or(DeserializeError::PropertyMissing)?;
                     params.push(param);
                 }
                 let info: crate::port::Info = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no info field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 Ok(PortUpdate {
                     direction,
                     port_id,
@@ -362,38 +471,38 @@ impl<'de> spa::deserialize::PodDeserialize<'de> for PortBuffers {
             ) -> Result<Self::Value, spa::deserialize::DeserializeError<&'de [u8]>> {
                 let direction: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no change_mask field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let port_id: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no change_mask field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let mix_id: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no change_mask field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let n_buffers: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no n_buffers field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let mut buffers: Vec<Buffer> = Vec::new();
                 for _ in 0..n_buffers {
                     let n_datas: i32 = struct_deserializer
                         .deserialize_field()?
-                        .expect("Input has no n_datas field");
+                        .ok_or(DeserializeError::PropertyMissing)?;
                     let mut data_planes: Vec<DataPlane> = Vec::with_capacity(n_datas as usize);
                     for _ in 0..n_datas {
                         let type_: spa::value::Id = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no type field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let memfd: spa::value::Fd = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no type field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let flags: i32 = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no type field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let mapoffset: i32 = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no type field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let maxsize: i32 = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no type field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         data_planes.push(DataPlane {
                             type_,
                             memfd,
@@ -426,6 +535,7 @@ impl spa::serialize::PodSerialize for UseBuffers {
         struct_serializer.serialize_field(&self.direction)?;
         struct_serializer.serialize_field(&self.port_id)?;
         struct_serializer.serialize_field(&self.mix_id)?;
+        struct_serializer.serialize_field(&self.flags)?;
         struct_serializer.serialize_field(&(self.buffers.len() as i32))?;
         for buffer in self.buffers.iter() {
             struct_serializer.serialize_field(&buffer.memid)?;
@@ -469,63 +579,63 @@ impl<'de> spa::deserialize::PodDeserialize<'de> for UseBuffers {
             ) -> Result<Self::Value, spa::deserialize::DeserializeError<&'de [u8]>> {
                 let direction: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no direction field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let port_id: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no port_id field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let mix_id: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no mix_id field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let flags: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no flags field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let n_buffers: i32 = struct_deserializer
                     .deserialize_field()?
-                    .expect("Input has no n_buffers field");
+                    .ok_or(DeserializeError::PropertyMissing)?;
                 let mut buffers: Vec<MixerBuffer> = Vec::new();
                 for _ in 0..n_buffers {
                     let memid: i32 = struct_deserializer
                         .deserialize_field()?
-                        .expect("Input has no memid field");
+                        .ok_or(DeserializeError::PropertyMissing)?;
                     let offset: i32 = struct_deserializer
                         .deserialize_field()?
-                        .expect("Input has no offset field");
+                        .ok_or(DeserializeError::PropertyMissing)?;
                     let size: i32 = struct_deserializer
                         .deserialize_field()?
-                        .expect("Input has no size field");
+                        .ok_or(DeserializeError::PropertyMissing)?;
                     let n_metas: i32 = struct_deserializer
                         .deserialize_field()?
-                        .expect("Input has no n_metas field");
+                        .ok_or(DeserializeError::PropertyMissing)?;
                     let mut metas: Vec<Meta> = Vec::with_capacity(n_metas as usize);
                     for _ in 0..n_metas {
                         let type_: spa::value::Id = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no type field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let size: i32 = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no type field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         metas.push(Meta { type_, size });
                     }
                     let n_datas: i32 = struct_deserializer
                         .deserialize_field()?
-                        .expect("Input has no n_datas field");
+                        .ok_or(DeserializeError::PropertyMissing)?;
                     let mut data_blocks: Vec<DataBlock> = Vec::with_capacity(n_datas as usize);
                     for _ in 0..n_datas {
                         let type_: spa::value::Id = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no type field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let data: i32 = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no data field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let flags: i32 = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no flags field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let mapoffset: i32 = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no mapoffset field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         let maxsize: i32 = struct_deserializer
                             .deserialize_field()?
-                            .expect("Input has no maxsize field");
+                            .ok_or(DeserializeError::PropertyMissing)?;
                         data_blocks.push(DataBlock {
                             type_,
                             data,
@@ -1,5 +1,7 @@
 use std::{
-    collections::HashMap, io::Error, ops::{Deref, DerefMut}, sync::Arc
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::Arc,
 };
 
 use spa::{
@@ -12,8 +14,10 @@ use spa_derive::{PodDeserialize, PodSerialize};
 use tokio::sync::Mutex;
 
 use crate::{
+    error::{DisconnectReason, MessageError},
+    proxy::{BindableProxy, PendingSync},
     registry::{self, RegistryProxy},
-    PipewireWriter, Proxies,
+    Fds, PipewireConnection, PipewireReaderHandle, PipewireWriter, Proxies,
 };
 
 pub const CORE_ID: i32 = 0;
@@ -23,6 +27,7 @@ pub struct CoreProxy {
     connection: Arc<Mutex<PipewireWriter>>,
     event_channel: tokio::sync::mpsc::Receiver<CoreEvent>,
     proxies: Arc<Mutex<Proxies>>,
+    reader: PipewireReaderHandle,
 }
 
 impl CoreProxy {
@@ -30,44 +35,61 @@ impl CoreProxy {
         connection: Arc<Mutex<PipewireWriter>>,
         event_channel: tokio::sync::mpsc::Receiver<CoreEvent>,
         proxies: Arc<Mutex<Proxies>>,
+        reader: PipewireReaderHandle,
+        version: i32,
     ) -> std::io::Result<CoreProxy> {
         let mut this = CoreProxy {
             connection,
             event_channel,
             proxies,
+            reader,
         };
-        this.hello().await?;
+        this.hello(version).await?;
         Ok(this)
     }
-    async fn hello(&mut self) -> Result<(), std::io::Error> {
+    async fn hello(&mut self, version: i32) -> Result<(), std::io::Error> {
         self.connection
             .lock()
             .await
-            .call_method(CORE_ID, Hello::OP_CODE, Hello { version: 3 })
+            .call_method(CORE_ID, Hello::OP_CODE, Hello { version })
             .await
     }
 
-    pub async fn sync(&mut self, id: i32) ->Result<(), std::io::Error>
-    {
-        let mut connection = self.connection.lock().await;
-        let seq = connection.seq + 1;
-        connection.call_method(CORE_ID, Sync::OP_CODE, Sync {
-            id: id,
-            seq: seq,
-        }).await
+    // A handle to the connection the core belongs to, e.g. for closing it
+    pub fn connection(&self) -> PipewireConnection {
+        PipewireConnection {
+            writer: self.connection.clone(),
+            reader: self.reader.clone(),
+            proxies: self.proxies.clone(),
+        }
     }
 
-    pub async fn get_registry(&mut self) -> std::io::Result<RegistryProxy> {
-        let mut id = 0;
-        let (sender, receiver) = tokio::sync::mpsc::channel(100);
+    // Send a sync for the proxy with id, the returned PendingSync can be awaited for the done event
+    pub async fn sync(&mut self, id: i32) -> std::io::Result<PendingSync> {
+        self.connection.lock().await.sync(&self.proxies, id).await
+    }
 
-        {
+    // Wait until the server has processed everything sent before it.
+    // Events received meanwhile are delivered to the proxy channels, they do not need to be read first
+    pub async fn roundtrip(&mut self) -> std::io::Result<()> {
+        self.sync(CORE_ID).await?.await
+    }
+
+    // The registry generation last received from the server, it grows with every new global
+    pub async fn generation(&self) -> u64 {
+        self.proxies.lock().await.generation
+    }
+
+    pub async fn get_registry(&mut self) -> std::io::Result<RegistryProxy> {
+        let (id, receiver) = {
             let mut connection = self.connection.lock().await;
             let mut proxies = self.proxies.lock().await;
-            proxies.id_counter = proxies.id_counter + 1;
-            id = proxies.id_counter;
-            proxies.registry_proxies.insert(id, sender);
-            connection
+            let (sender, receiver) = tokio::sync::mpsc::channel(proxies.capacities.registry);
+            let id = proxies.allocate_id();
+            proxies
+                .registry_proxies
+                .insert(id, crate::queue::EventSender::new(sender));
+            let result = connection
                 .call_method(
                     CORE_ID,
                     GetRegistry::OP_CODE,
@@ -76,13 +98,71 @@ impl CoreProxy {
                         new_id: id,
                     },
                 )
-                .await?;
-        }
+                .await;
+            if let Err(e) = result {
+                proxies.unregister(id);
+                return Err(e);
+            }
+            (id, receiver)
+        };
+
+        Ok(registry::RegistryProxy::from_parts(
+            id,
+            self.connection.clone(),
+            receiver,
+            self.proxies.clone(),
+        ))
+    }
+
+    // Create an object with the factory factory_name, e.g. a LinkProxy with "link-factory" or
+    // a NodeProxy with "adapter". The interface type and version are taken from P.
+    // The object is destroyed when the connection closes, unless "object.linger" is "true" in props
+    pub async fn create_object<P: BindableProxy>(
+        &mut self,
+        factory_name: &str,
+        props: HashMap<String, String>,
+    ) -> std::io::Result<P> {
+        let (id, receiver, pending_sync) = {
+            let mut connection = self.connection.lock().await;
+            let (id, receiver) = {
+                let mut proxies = self.proxies.lock().await;
+                let (sender, receiver) =
+                    tokio::sync::mpsc::channel(P::channel_capacity(&proxies.capacities));
+                let id = proxies.allocate_id();
+                P::register(&mut proxies, id, sender);
+                (id, receiver)
+            };
+            let result = connection
+                .call_constructor_checked(
+                    &self.proxies,
+                    CORE_ID,
+                    CreateObject::OP_CODE,
+                    CreateObject {
+                        factory_name: factory_name.to_string(),
+                        type_: P::TYPE.to_string(),
+                        version: P::VERSION,
+                        props,
+                        new_id: id,
+                    },
+                    id,
+                )
+                .await;
+            match result {
+                Ok(pending_sync) => (id, receiver, pending_sync),
+                Err(e) => {
+                    self.proxies.lock().await.unregister(id);
+                    return Err(e);
+                }
+            }
+        };
+        // Errors are reported on the new id, the server removes the id again when it fails
+        pending_sync.await?;
 
-        Ok(registry::RegistryProxy::new(
+        Ok(P::new(
             id,
             self.connection.clone(),
             receiver,
+            self.proxies.clone(),
         ))
     }
 }
@@ -159,13 +239,22 @@ pub struct Destroy {
 pub enum CoreEvent {
     Info(Info),
     Done(Done),
-    Ping(Ping),
+    Ping(Ping), // Already answered with a Pong by the connection, passed on for diagnostics
     Error(ErrorEvent),
     RemoveId(RemoveId),
     BoundId(BoundId),
-    AddMem(AddMem),
+    // The memfd of AddMem is an index into the fds that was sent along with the event
+    AddMem(AddMem, Fds),
     RemoveMem(RemoveMem),
     BoundProps(BoundProps),
+    // A message from the server could not be passed on and was skipped
+    SkippedMessage(MessageError),
+    // The connection was lost and is being reestablished, Reconnected follows if it succeeds
+    ConnectionLost(DisconnectReason),
+    // The connection was restored and the core is usable again
+    Reconnected,
+    // The connection is gone, this is the last event the core receives
+    Disconnected(DisconnectReason),
 }
 impl spa::opcode::DeserializeFromOpCode for CoreEvent {
     fn deserialize_from_opcode(
@@ -203,7 +292,7 @@ impl spa::opcode::DeserializeFromOpCode for CoreEvent {
             }
             AddMem::OP_CODE => {
                 let (remain, value): (&[u8], AddMem) = PodDeserializer::deserialize_from(buffer)?;
-                Ok((remain, CoreEvent::AddMem(value)))
+                Ok((remain, CoreEvent::AddMem(value, Fds::default())))
             }
             RemoveMem::OP_CODE => {
                 let (remain, value): (&[u8], RemoveMem) =
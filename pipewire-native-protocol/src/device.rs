@@ -1,24 +1,106 @@
 use std::collections::HashMap;
 
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
-// === Methods ===
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+    value::{Id, Object, Property, PropertyFlags, Value, ValueArray},
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+use tokio::io;
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct SubscribeParams {
-    pub ids: Vec<spa::value::Id>,
+use crate::{
+    param::{self, ParamInfos},
+    proxy,
+};
+
+proxy::define_proxy! {
+    pub struct DeviceProxy: DeviceEvent {}
+    params(DeviceEvent::Param)
+    bindable("PipeWire:Interface:Device", 3, device, device_proxies)
 }
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct EnumParams {
-    pub seq: i32,
-    pub id: spa::value::Id,
-    pub index: i32,
-    pub num: i32,
-    pub filter: spa::value::Value,
+impl DeviceProxy {
+    pub async fn set_param(&self, id: Id, flags: i32, param: Value) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(
+                &self.proxies,
+                self.id,
+                SetParam::OP_CODE,
+                SetParam { id, flags, param },
+            )
+            .await?;
+        pending_sync.await
+    }
+
+    // All profiles the device supports
+    pub async fn enum_profiles(&mut self) -> io::Result<Vec<Profile>> {
+        let params = self
+            .enum_params(param::PARAM_ENUM_PROFILE, Value::None)
+            .await?;
+        Ok(params.iter().filter_map(Profile::from_param).collect())
+    }
+
+    // The active profile, if any
+    pub async fn profile(&mut self) -> io::Result<Option<Profile>> {
+        let params = self.enum_params(param::PARAM_PROFILE, Value::None).await?;
+        Ok(params.iter().find_map(Profile::from_param))
+    }
+
+    // Switch to the profile with the given index, as found in Profile::index
+    pub async fn set_profile(&self, index: i32) -> io::Result<()> {
+        let profile = Object {
+            type_: param::OBJECT_PARAM_PROFILE,
+            id: param::PARAM_PROFILE.0,
+            properties: vec![
+                property(Profile::KEY_INDEX, Value::Int(index)),
+                property(Profile::KEY_SAVE, Value::Bool(true)),
+            ],
+        };
+        self.set_param(param::PARAM_PROFILE, 0, Value::Object(profile))
+            .await
+    }
+
+    // All routes the device supports
+    pub async fn enum_routes(&mut self) -> io::Result<Vec<Route>> {
+        let params = self
+            .enum_params(param::PARAM_ENUM_ROUTE, Value::None)
+            .await?;
+        Ok(params.iter().filter_map(Route::from_param).collect())
+    }
+
+    // The active routes, one for each device in the active profile
+    pub async fn routes(&mut self) -> io::Result<Vec<Route>> {
+        let params = self.enum_params(param::PARAM_ROUTE, Value::None).await?;
+        Ok(params.iter().filter_map(Route::from_param).collect())
+    }
+
+    // Switch the device with the given id to the route with the given index.
+    // The device ids a route can be used on are found in Route::devices
+    pub async fn set_route(&self, index: i32, device: i32) -> io::Result<()> {
+        let route = Object {
+            type_: param::OBJECT_PARAM_ROUTE,
+            id: param::PARAM_ROUTE.0,
+            properties: vec![
+                property(Route::KEY_INDEX, Value::Int(index)),
+                property(Route::KEY_DEVICE, Value::Int(device)),
+                property(Route::KEY_SAVE, Value::Bool(true)),
+            ],
+        };
+        self.set_param(param::PARAM_ROUTE, 0, Value::Object(route))
+            .await
+    }
 }
 
+// === Methods ===
+// The methods for params and the Param event are shared by nodes, ports and devices
+pub use crate::param::{EnumParams, Param, SubscribeParams};
+
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(3)]
 pub struct SetParam {
     pub id: spa::value::Id,
     pub flags: i32,
@@ -26,23 +108,177 @@ pub struct SetParam {
 }
 
 // === Events ===
-pub enum DeviceEvent {
-    Info(Info),
-    Param(Param)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum DeviceEvent {
+        Info(Info),
+        Param(Param),
+    }
 }
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(0)]
 pub struct Info {
     pub id: i32,
     pub change_mask: i64,
     pub props: HashMap<String, String>,
-    pub param_info: HashMap<i32, i32>,
+    pub param_info: ParamInfos,
 }
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct Param {
-    pub seq: i32,
-    pub id: spa::value::Id,
+impl opcode::DeserializeFromOpCode for DeviceEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Info::OP_CODE => {
+                let (remain, value): (&[u8], Info) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, DeviceEvent::Info(value)))
+            }
+            Param::OP_CODE => {
+                let (remain, value): (&[u8], Param) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, DeviceEvent::Param(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
+}
+
+// === Params ===
+// A profile of the device, from an EnumProfile or Profile param
+#[derive(Debug, Clone, PartialEq)]
+pub struct Profile {
     pub index: i32,
-    pub next: i32,
-    pub param: spa::value::Value,
+    pub name: String,
+    pub description: Option<String>,
+    pub priority: i32,
+    pub available: Id, // One of param::AVAILABILITY_*
+}
+
+impl Profile {
+    // Keys of the properties in a ParamProfile object, see spa_param_profile
+    pub const KEY_INDEX: u32 = 1;
+    pub const KEY_NAME: u32 = 2;
+    pub const KEY_DESCRIPTION: u32 = 3;
+    pub const KEY_PRIORITY: u32 = 4;
+    pub const KEY_AVAILABLE: u32 = 5;
+    pub const KEY_INFO: u32 = 6;
+    pub const KEY_CLASSES: u32 = 7;
+    pub const KEY_SAVE: u32 = 8;
+
+    // Parse the param, returns None if it is not a profile
+    pub fn from_param(param: &Param) -> Option<Profile> {
+        let object = param_object(param, param::OBJECT_PARAM_PROFILE)?;
+        Some(Profile {
+            index: int_property(object, Self::KEY_INDEX)?,
+            name: string_property(object, Self::KEY_NAME)?,
+            description: string_property(object, Self::KEY_DESCRIPTION),
+            priority: int_property(object, Self::KEY_PRIORITY).unwrap_or_default(),
+            available: id_property(object, Self::KEY_AVAILABLE)
+                .unwrap_or(param::AVAILABILITY_UNKNOWN),
+        })
+    }
+}
+
+// A route of the device, e.g. speakers or headphones, from an EnumRoute or Route param
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub index: i32,
+    pub direction: Id,       // 0 is input and 1 is output
+    pub device: Option<i32>, // Only set for active routes
+    pub name: String,
+    pub description: Option<String>,
+    pub priority: i32,
+    pub available: Id,        // One of param::AVAILABILITY_*
+    pub profiles: Vec<i32>,   // Indices of the profiles the route is available in
+    pub devices: Vec<i32>,    // Ids of the devices the route can be used on
+    pub profile: Option<i32>, // Only set for active routes
+}
+
+impl Route {
+    // Keys of the properties in a ParamRoute object, see spa_param_route
+    pub const KEY_INDEX: u32 = 1;
+    pub const KEY_DIRECTION: u32 = 2;
+    pub const KEY_DEVICE: u32 = 3;
+    pub const KEY_NAME: u32 = 4;
+    pub const KEY_DESCRIPTION: u32 = 5;
+    pub const KEY_PRIORITY: u32 = 6;
+    pub const KEY_AVAILABLE: u32 = 7;
+    pub const KEY_INFO: u32 = 8;
+    pub const KEY_PROFILES: u32 = 9;
+    pub const KEY_PROPS: u32 = 10;
+    pub const KEY_DEVICES: u32 = 11;
+    pub const KEY_PROFILE: u32 = 12;
+    pub const KEY_SAVE: u32 = 13;
+
+    // Parse the param, returns None if it is not a route
+    pub fn from_param(param: &Param) -> Option<Route> {
+        let object = param_object(param, param::OBJECT_PARAM_ROUTE)?;
+        Some(Route {
+            index: int_property(object, Self::KEY_INDEX)?,
+            direction: id_property(object, Self::KEY_DIRECTION)?,
+            device: int_property(object, Self::KEY_DEVICE),
+            name: string_property(object, Self::KEY_NAME)?,
+            description: string_property(object, Self::KEY_DESCRIPTION),
+            priority: int_property(object, Self::KEY_PRIORITY).unwrap_or_default(),
+            available: id_property(object, Self::KEY_AVAILABLE)
+                .unwrap_or(param::AVAILABILITY_UNKNOWN),
+            profiles: int_array_property(object, Self::KEY_PROFILES),
+            devices: int_array_property(object, Self::KEY_DEVICES),
+            profile: int_property(object, Self::KEY_PROFILE),
+        })
+    }
+}
+
+fn property(key: u32, value: Value) -> Property {
+    Property {
+        key,
+        flags: PropertyFlags::empty(),
+        value,
+    }
+}
+
+fn param_object(param: &Param, type_: u32) -> Option<&Object> {
+    match &param.param {
+        Value::Object(object) if object.type_ == type_ => Some(object),
+        _ => None,
+    }
+}
+
+fn find_property(object: &Object, key: u32) -> Option<&Value> {
+    object
+        .properties
+        .iter()
+        .find(|property| property.key == key)
+        .map(|property| &property.value)
+}
+
+fn int_property(object: &Object, key: u32) -> Option<i32> {
+    match find_property(object, key)? {
+        Value::Int(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn id_property(object: &Object, key: u32) -> Option<Id> {
+    match find_property(object, key)? {
+        Value::Id(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn string_property(object: &Object, key: u32) -> Option<String> {
+    match find_property(object, key)? {
+        Value::String(value) => Some(value.clone()),
+        _ => None,
+    }
+}
+
+fn int_array_property(object: &Object, key: u32) -> Vec<i32> {
+    match find_property(object, key) {
+        Some(Value::ValueArray(ValueArray::Int(values))) => values.clone(),
+        _ => Vec::new(),
+    }
 }
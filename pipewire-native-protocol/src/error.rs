@@ -1,3 +1,5 @@
+use std::{io, sync::Arc};
+
 use core_proxy::CoreEvent;
 use spa::deserialize::DeserializeError;
 
@@ -11,6 +13,9 @@ pub(crate) enum PipewireConnectionError {
     ProxyNotPresentError(i32),
     #[error("Could not deserialize message")]
     DeserializeError(DeserializeError<Vec<u8>>),
+    #[error("Could not write to the connection")]
+    IoError(#[from] io::Error),
+    #[allow(dead_code)]
     #[error("Unknow error, likely a bug in the library")]
     Unknown,
 }
@@ -28,3 +33,121 @@ impl From<DeserializeError<&[u8]>> for PipewireConnectionError  {
         }
     }
 }
+
+// Errno style error codes sent in the res field of core error events, as negative numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Errno {
+    Perm,       // EPERM, operation not permitted
+    NoEnt,      // ENOENT, no such object
+    Io,         // EIO
+    Again,      // EAGAIN, try again
+    NoMem,      // ENOMEM, out of memory
+    Access,     // EACCES, permission denied
+    Busy,       // EBUSY, object is busy
+    Exist,      // EEXIST, object already exists
+    NoDev,      // ENODEV, no such device
+    Inval,      // EINVAL, invalid argument
+    Pipe,       // EPIPE, broken pipe
+    NoSys,      // ENOSYS, method not implemented
+    Proto,      // EPROTO, protocol error
+    NotSup,     // EOPNOTSUPP, operation not supported
+    TimedOut,   // ETIMEDOUT
+    Other(i32), // Any other errno value
+}
+
+impl Errno {
+    // Map a res value, which is a negative errno, to an Errno
+    pub fn from_res(res: i32) -> Errno {
+        match -res {
+            1 => Errno::Perm,
+            2 => Errno::NoEnt,
+            5 => Errno::Io,
+            11 => Errno::Again,
+            12 => Errno::NoMem,
+            13 => Errno::Access,
+            16 => Errno::Busy,
+            17 => Errno::Exist,
+            19 => Errno::NoDev,
+            22 => Errno::Inval,
+            32 => Errno::Pipe,
+            38 => Errno::NoSys,
+            71 => Errno::Proto,
+            95 => Errno::NotSup,
+            110 => Errno::TimedOut,
+            errno => Errno::Other(errno),
+        }
+    }
+
+    fn io_error_kind(self) -> io::ErrorKind {
+        match self {
+            Errno::Perm | Errno::Access => io::ErrorKind::PermissionDenied,
+            Errno::NoEnt | Errno::NoDev => io::ErrorKind::NotFound,
+            Errno::Again => io::ErrorKind::WouldBlock,
+            Errno::NoMem => io::ErrorKind::OutOfMemory,
+            Errno::Exist => io::ErrorKind::AlreadyExists,
+            Errno::Inval => io::ErrorKind::InvalidInput,
+            Errno::Pipe => io::ErrorKind::BrokenPipe,
+            Errno::NoSys | Errno::NotSup => io::ErrorKind::Unsupported,
+            Errno::Proto => io::ErrorKind::InvalidData,
+            Errno::TimedOut => io::ErrorKind::TimedOut,
+            Errno::Io | Errno::Busy | Errno::Other(_) => io::ErrorKind::Other,
+        }
+    }
+}
+
+// An error reported by the server for a proxy, through a core error event.
+// seq is the seq of the method call that failed
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Error on proxy {id} ({errno:?}): {message}")]
+pub struct ServerError {
+    pub id: i32,
+    pub seq: i32,
+    pub res: i32,
+    pub errno: Errno,
+    pub message: String,
+}
+
+impl ServerError {
+    // Find the ServerError inside an io::Error returned by a method call
+    pub fn from_io_error(error: &io::Error) -> Option<&ServerError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl From<core_proxy::ErrorEvent> for ServerError {
+    fn from(event: core_proxy::ErrorEvent) -> Self {
+        ServerError {
+            id: event.id,
+            seq: event.seq,
+            res: event.res,
+            errno: Errno::from_res(event.res),
+            message: event.message,
+        }
+    }
+}
+
+impl From<ServerError> for io::Error {
+    fn from(error: ServerError) -> Self {
+        io::Error::new(error.errno.io_error_kind(), error)
+    }
+}
+
+// A message from the server that was skipped, reported to the core as CoreEvent::SkippedMessage
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum MessageError {
+    #[error("Message {opcode} for proxy {id} could not be decoded")]
+    Invalid { id: i32, opcode: u32 },
+    #[error("Message {opcode} for proxy {id}, which does not exist")]
+    UnknownProxy { id: i32, opcode: u32 },
+}
+
+// Why a connection stopped, delivered as the last event to every proxy of the connection
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum DisconnectReason {
+    #[error("Connection was closed")]
+    Closed, // By PipewireConnection::close
+    #[error("Server closed the connection")]
+    ServerClosed,
+    #[error("Connection failed: {0}")]
+    Error(Arc<io::Error>), // Reading from the socket failed
+}
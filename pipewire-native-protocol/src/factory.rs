@@ -1,19 +1,18 @@
-use std::{collections::HashMap, sync::{Arc, Mutex}};
+use std::collections::HashMap;
 
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
 
-use crate::PipewireWriter;
+use crate::proxy;
 
-pub struct FactoryProxy {
-    connection: Arc<Mutex<PipewireWriter>>,
-    event_receiver: std::sync::mpsc::Receiver<FactoryEvent>
-}
-
-impl FactoryProxy {
-    pub(crate) fn new(connection: Arc<Mutex<PipewireWriter>>, event_receiver: std::sync::mpsc::Receiver<FactoryEvent>) -> FactoryProxy{
-        FactoryProxy {connection, event_receiver }
-    }
+proxy::define_proxy! {
+    pub struct FactoryProxy: FactoryEvent {}
+    info(Info, FactoryEvent::Info)
+    bindable("PipeWire:Interface:Factory", 3, factory, factory_proxies)
 }
 
 // === Methods ===
@@ -21,15 +20,55 @@ impl FactoryProxy {
 // Factory has no methods
 
 // === Events ===
-pub enum FactoryEvent {
-    FactoryEvent(Info)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum FactoryEvent {
+        Info(Info),
+    }
 }
-#[derive(PodSerialize, PodDeserialize, Debug)]
+#[derive(PodSerialize, PodDeserialize, Debug, Clone, PartialEq)]
+#[opcode(0)]
 pub struct Info {
     pub id: i32,
     pub name: String,
-    pub type_: String,
+    pub type_: String, // The interface of the objects the factory creates
     pub version: i32,
-    pub change_mask: i64,
+    pub change_mask: i64, // Which fields changed since the previous info, see CHANGE_MASK_*
     pub props: HashMap<String, String>,
 }
+
+impl Info {
+    pub const CHANGE_MASK_PROPS: i64 = 1 << 0;
+    pub const CHANGE_MASK_ALL: i64 = Self::CHANGE_MASK_PROPS;
+}
+
+// The name, type and version do not change for a factory
+impl proxy::MergeInfo for Info {
+    const CHANGE_MASK_PROPS: i64 = Self::CHANGE_MASK_PROPS;
+
+    fn change_mask(&mut self) -> &mut i64 {
+        &mut self.change_mask
+    }
+
+    fn props(&mut self) -> &mut HashMap<String, String> {
+        &mut self.props
+    }
+}
+
+impl opcode::DeserializeFromOpCode for FactoryEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Info::OP_CODE => {
+                let (remain, value): (&[u8], Info) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, FactoryEvent::Info(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+// Footers are an optional struct pod after the payload of a message, for data that is not part of
+// the method or event itself. The struct holds pairs of an opcode and a struct of arguments.
+// The server sends the registry generation, which is incremented for every new global, and the
+// client sends back the generation it has seen. The server uses it to refuse binding a global
+// that the client does not know yet, e.g. one that reused the id of a global that was removed
+use std::io::Cursor;
+
+use spa::{
+    deserialize::PodDeserializer,
+    serialize::PodSerializer,
+    value::{Id, Value},
+};
+
+// Footer opcodes from the server, the argument is the generation as a Long
+pub const FOOTER_CORE_GENERATION: u32 = 0;
+// Footer opcodes from the client, the argument is the last generation received as a Long
+pub const FOOTER_CLIENT_GENERATION: u32 = 0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footer {
+    pub opcode: u32,
+    pub arguments: Vec<Value>,
+}
+
+// Split a payload into the pod of the message and the footer after it, which may be empty
+pub fn split(payload: &[u8]) -> (&[u8], &[u8]) {
+    let Some(size) = payload.get(..4) else {
+        return (payload, &[]);
+    };
+    let size = u32::from_ne_bytes(size.try_into().unwrap()) as usize;
+    // The pod header is 8 bytes and pods are padded to 8 bytes
+    let end = (8 + size).next_multiple_of(8).min(payload.len());
+    payload.split_at(end)
+}
+
+// Decode the footers, unknown content is skipped rather than failing the message
+pub fn parse(bytes: &[u8]) -> Vec<Footer> {
+    let Ok((_, Value::Struct(fields))) = PodDeserializer::deserialize_any_from(bytes) else {
+        return Vec::new();
+    };
+    fields
+        .chunks_exact(2)
+        .filter_map(|pair| match pair {
+            [Value::Id(Id(opcode)), Value::Struct(arguments)] => Some(Footer {
+                opcode: *opcode,
+                arguments: arguments.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// The generation in the footer with opcode, FOOTER_CORE_GENERATION or FOOTER_CLIENT_GENERATION
+pub fn generation(footers: &[Footer], opcode: u32) -> Option<u64> {
+    footers
+        .iter()
+        .find(|footer| footer.opcode == opcode)
+        .and_then(|footer| match footer.arguments.first() {
+            Some(Value::Long(generation)) => Some(*generation as u64),
+            _ => None,
+        })
+}
+
+// The footer telling the server which generation the client has seen
+pub(crate) fn client_generation(generation: u64) -> Vec<u8> {
+    let footer = Value::Struct(vec![
+        Value::Id(Id(FOOTER_CLIENT_GENERATION)),
+        Value::Struct(vec![Value::Long(generation as i64)]),
+    ]);
+    PodSerializer::serialize(Cursor::new(Vec::new()), &footer)
+        .unwrap()
+        .0
+        .into_inner()
+}
@@ -1,23 +1,44 @@
+pub mod builder;
 pub mod client;
 pub mod client_node;
 pub mod core_proxy;
 pub mod device;
-mod error;
+pub mod error;
 pub mod factory;
+pub mod footer;
 pub mod link;
 pub mod metadata;
+#[cfg(feature = "test-support")]
+pub mod mock;
 pub mod module;
 pub mod node;
+pub mod param;
+pub mod permissions;
 pub mod port;
 pub mod profiler;
 pub mod proxy;
+pub mod reconnect;
+pub mod record;
 pub mod registry;
+pub mod remote;
+pub mod replay;
+pub mod security_context;
+mod queue;
+mod socket;
+mod tap;
+#[cfg(feature = "tracing")]
+mod trace;
 
-use std::{collections::HashMap, io::Cursor, sync::Arc};
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    sync::Arc,
+};
 
 use client::{ClientEvent, ClientProxy};
 use client_node::ClientNodeEvent;
-use core_proxy::{CoreEvent, Done, Ping};
+use core_proxy::{CoreEvent, Done};
 use device::DeviceEvent;
 use factory::FactoryEvent;
 use link::LinkEvent;
@@ -26,23 +47,18 @@ use module::ModuleEvent;
 use node::NodeEvent;
 use port::PortEvent;
 use profiler::ProfilerEvent;
+use proxy::internal::ProxyEvent;
 use registry::RegistryEvent;
+use security_context::SecurityContextEvent;
 use spa::{
-    deserialize::DeserializeError,
     opcode::DeserializeFromOpCode,
     serialize::{PodSerialize, PodSerializer},
 };
-use tokio::{
-    io::{self, AsyncReadExt, AsyncWriteExt},
-    sync::Mutex,
-};
-use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes};
+use tokio::{io, sync::Mutex};
+use zerocopy::{FromBytes, Immutable, IntoBytes};
 
-use tokio_stream::StreamExt;
-use tokio_util::{
-    bytes::BytesMut,
-    codec::{Framed, FramedRead, LengthDelimitedCodec},
-};
+use tokio_util::bytes::BytesMut;
+#[derive(Clone)]
 pub struct PipewireConnection {
     writer: Arc<Mutex<PipewireWriter>>,
     reader: PipewireReaderHandle,
@@ -51,41 +67,398 @@ pub struct PipewireConnection {
 pub(crate) struct PipewireWriter {
     stream: tokio::net::unix::OwnedWriteHalf,
     seq: i32,
+    tap: Option<tap::SharedTap>, // Dumps every message sent
+    recorder: Option<record::SharedRecorder>,
+    recv_generation: u64,         // The last registry generation the server sent
+    sent_generation: u64,         // The generation in the last footer sent
+    next_generation: Option<u64>, // Sent instead of recv_generation with the next message
 }
 
-enum PipewireReaderMessage {}
+enum PipewireReaderMessage {
+    Close(tokio::sync::oneshot::Sender<()>), // Stop reading, answered when the proxies are told
+}
 
-#[derive(Debug)]
 struct PipewireReader {
-    stream: FramedRead<tokio::net::unix::OwnedReadHalf, LengthDelimitedCodec>,
+    stream: socket::SocketReader,
     control: tokio::sync::mpsc::Receiver<PipewireReaderMessage>,
     proxies: Arc<Mutex<Proxies>>,
+    writer: Arc<Mutex<PipewireWriter>>, // Used for answering pings
+    reconnect: Option<reconnect::Reconnect>, // Set when a lost connection should be reestablished
+    tap: Option<tap::SharedTap>,        // Dumps every message received
+    recorder: Option<record::SharedRecorder>,
 }
 
 // The collection of proxies currently active on a connection
 #[derive(Debug)]
-struct Proxies {
-    id_counter: i32, // Gets increment each time a new proxy is allocated
-    core_proxy: Option<tokio::sync::mpsc::Sender<CoreEvent>>,
-    client_proxy: Option<tokio::sync::mpsc::Sender<ClientEvent>>,
-    registry_proxies: HashMap<i32, tokio::sync::mpsc::Sender<RegistryEvent>>,
-    device_proxies: HashMap<i32, tokio::sync::mpsc::Sender<DeviceEvent>>,
-    factory_proxies: HashMap<i32, tokio::sync::mpsc::Sender<FactoryEvent>>,
-    link_proxies: HashMap<i32, tokio::sync::mpsc::Sender<LinkEvent>>,
-    module_proxies: HashMap<i32, tokio::sync::mpsc::Sender<ModuleEvent>>,
-    node_proxies: HashMap<i32, tokio::sync::mpsc::Sender<NodeEvent>>,
-    port_proxies: HashMap<i32, tokio::sync::mpsc::Sender<PortEvent>>,
-    client_node_proxies: HashMap<i32, tokio::sync::mpsc::Sender<ClientNodeEvent>>,
-    metadata_proxies: HashMap<i32, tokio::sync::mpsc::Sender<MetadataEvent>>,
-    profiler_proxies: HashMap<i32, tokio::sync::mpsc::Sender<ProfilerEvent>>,
+pub(crate) struct Proxies {
+    id_counter: i32,              // Gets increment each time a new proxy is allocated
+    free_ids: Vec<i32>, // Ids the server has removed, reused before incrementing id_counter
+    bound_ids: HashMap<i32, i32>, // Proxy id to the id of the global it is bound to
+    pending_syncs: HashMap<(i32, i32), PendingSyncSender>, // Keyed by (id, seq) of the sync
+    bindings: HashMap<i32, reconnect::Binding>, // Proxy id to the global it was bound from
+    generation: u64,    // The registry generation from the footers of the server
+    removed_globals: HashMap<i32, u64>, // Global id to the generation it was removed in, if newer
+    capacities: builder::ChannelCapacities, // Sizes of the event channels of new proxies
+    core_proxy: Option<queue::EventSender<CoreEvent>>,
+    client_proxies: HashMap<i32, queue::EventSender<ClientEvent>>, // Our own at CLIENT_ID
+    registry_proxies: HashMap<i32, queue::EventSender<RegistryEvent>>,
+    device_proxies: HashMap<i32, queue::EventSender<DeviceEvent>>,
+    factory_proxies: HashMap<i32, queue::EventSender<FactoryEvent>>,
+    link_proxies: HashMap<i32, queue::EventSender<LinkEvent>>,
+    module_proxies: HashMap<i32, queue::EventSender<ModuleEvent>>,
+    node_proxies: HashMap<i32, queue::EventSender<NodeEvent>>,
+    port_proxies: HashMap<i32, queue::EventSender<PortEvent>>,
+    client_node_proxies: HashMap<i32, queue::EventSender<ClientNodeEvent>>,
+    metadata_proxies: HashMap<i32, queue::EventSender<MetadataEvent>>,
+    profiler_proxies: HashMap<i32, queue::EventSender<ProfilerEvent>>,
+    security_context_proxies: HashMap<i32, queue::EventSender<SecurityContextEvent>>,
+}
+
+impl Proxies {
+    // Allocate the id for a new proxy
+    fn allocate_id(&mut self) -> i32 {
+        self.free_ids.pop().unwrap_or_else(|| {
+            self.id_counter += 1;
+            self.id_counter
+        })
+    }
+
+    // Undo registering the proxy with id when the message creating it could not be sent,
+    // the server never saw the id so it can be used again right away
+    fn unregister(&mut self, id: i32) {
+        self.remove(id);
+        self.free_ids.push(id);
+    }
+
+    // Fail the syncs that were sent after the method call that caused the error on the same proxy.
+    // Calls that create an object sync on the new id, which is the one their errors are reported on
+    fn fail_pending_syncs(&mut self, error: &error::ServerError) {
+        let failed: Vec<(i32, i32)> = self
+            .pending_syncs
+            .keys()
+            .filter(|(id, seq)| *id == error.id && *seq > error.seq)
+            .copied()
+            .collect();
+        for key in failed {
+            if let Some(pending_sync) = self.pending_syncs.remove(&key) {
+                let _ = pending_sync.send(Err(error.clone()));
+            }
+        }
+    }
+
+    // Stamp globals with the generation they were received in and remember when they are removed
+    fn track_global(&mut self, event: &mut RegistryEvent) {
+        match event {
+            RegistryEvent::Global(global) => {
+                global.generation = self.generation;
+                // The server refuses to bind a global older than the generation the bind was sent
+                // with, so only the removals since the last new global need to be kept. Without
+                // generations from the server a new global can not be told from a stale one
+                let generation = self.generation;
+                self.removed_globals
+                    .retain(|id, removed| *removed >= generation && *id != global.id);
+            }
+            RegistryEvent::GlobalRemove(remove) => {
+                self.removed_globals.insert(remove.id, self.generation);
+            }
+            _ => (),
+        }
+    }
+
+    // Whether the global was removed after it was received
+    fn is_stale(&self, global: &registry::Global) -> bool {
+        self.removed_globals
+            .get(&global.id)
+            .is_some_and(|removed| *removed >= global.generation)
+    }
+
+    // The server has removed the id, forget the proxy and make the id available again
+    fn free_id(&mut self, id: i32) {
+        if id == core_proxy::CORE_ID || id == ClientProxy::CLIENT_ID || id > self.id_counter {
+            return;
+        }
+        self.remove(id);
+        self.bound_ids.remove(&id);
+        if !self.free_ids.contains(&id) {
+            self.free_ids.push(id);
+        }
+    }
+
+    // Find the event channel of the proxy with the given id
+    fn sender(&self, id: i32) -> Option<ProxySender> {
+        let sender = if id == core_proxy::CORE_ID {
+            return None; // Core events are handled by the reader itself
+        } else if let Some(sender) = self.client_proxies.get(&id) {
+            ProxySender::Client(sender.clone())
+        } else if let Some(sender) = self.registry_proxies.get(&id) {
+            ProxySender::Registry(sender.clone())
+        } else if let Some(sender) = self.device_proxies.get(&id) {
+            ProxySender::Device(sender.clone())
+        } else if let Some(sender) = self.factory_proxies.get(&id) {
+            ProxySender::Factory(sender.clone())
+        } else if let Some(sender) = self.link_proxies.get(&id) {
+            ProxySender::Link(sender.clone())
+        } else if let Some(sender) = self.module_proxies.get(&id) {
+            ProxySender::Module(sender.clone())
+        } else if let Some(sender) = self.node_proxies.get(&id) {
+            ProxySender::Node(sender.clone())
+        } else if let Some(sender) = self.port_proxies.get(&id) {
+            ProxySender::Port(sender.clone())
+        } else if let Some(sender) = self.client_node_proxies.get(&id) {
+            ProxySender::ClientNode(sender.clone())
+        } else if let Some(sender) = self.metadata_proxies.get(&id) {
+            ProxySender::Metadata(sender.clone())
+        } else if let Some(sender) = self.profiler_proxies.get(&id) {
+            ProxySender::Profiler(sender.clone())
+        } else if let Some(sender) = self.security_context_proxies.get(&id) {
+            ProxySender::SecurityContext(sender.clone())
+        } else {
+            return None;
+        };
+        Some(sender)
+    }
+
+    // Forget about the proxy with the given id, no more events are routed to it
+    fn remove(&mut self, id: i32) {
+        match id {
+            core_proxy::CORE_ID => self.core_proxy = None,
+            id => {
+                self.bindings.remove(&id);
+                self.client_proxies.remove(&id);
+                self.registry_proxies.remove(&id);
+                self.device_proxies.remove(&id);
+                self.factory_proxies.remove(&id);
+                self.link_proxies.remove(&id);
+                self.module_proxies.remove(&id);
+                self.node_proxies.remove(&id);
+                self.port_proxies.remove(&id);
+                self.client_node_proxies.remove(&id);
+                self.metadata_proxies.remove(&id);
+                self.profiler_proxies.remove(&id);
+                self.security_context_proxies.remove(&id);
+            }
+        }
+    }
+
+    // The channels of every proxy except the core
+    fn senders(&self) -> Vec<ProxySender> {
+        let mut senders = Vec::new();
+        senders.extend(
+            self.client_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::Client),
+        );
+        senders.extend(
+            self.registry_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::Registry),
+        );
+        senders.extend(
+            self.device_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::Device),
+        );
+        senders.extend(
+            self.factory_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::Factory),
+        );
+        senders.extend(self.link_proxies.values().cloned().map(ProxySender::Link));
+        senders.extend(
+            self.module_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::Module),
+        );
+        senders.extend(self.node_proxies.values().cloned().map(ProxySender::Node));
+        senders.extend(self.port_proxies.values().cloned().map(ProxySender::Port));
+        senders.extend(
+            self.client_node_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::ClientNode),
+        );
+        senders.extend(
+            self.metadata_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::Metadata),
+        );
+        senders.extend(
+            self.profiler_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::Profiler),
+        );
+        senders.extend(
+            self.security_context_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::SecurityContext),
+        );
+        senders
+    }
+
+    // Take the channels of every proxy, the connection is gone and nothing is routed anymore
+    fn disconnect(
+        &mut self,
+    ) -> (Option<queue::EventSender<CoreEvent>>, Vec<ProxySender>) {
+        // No done events will arrive anymore, dropping the senders makes the pending syncs fail
+        self.pending_syncs.clear();
+        let mut senders = Vec::new();
+        senders.extend(
+            self.client_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::Client(s)),
+        );
+        senders.extend(
+            self.registry_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::Registry(s)),
+        );
+        senders.extend(
+            self.device_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::Device(s)),
+        );
+        senders.extend(
+            self.factory_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::Factory(s)),
+        );
+        senders.extend(self.link_proxies.drain().map(|(_, s)| ProxySender::Link(s)));
+        senders.extend(
+            self.module_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::Module(s)),
+        );
+        senders.extend(self.node_proxies.drain().map(|(_, s)| ProxySender::Node(s)));
+        senders.extend(self.port_proxies.drain().map(|(_, s)| ProxySender::Port(s)));
+        senders.extend(
+            self.client_node_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::ClientNode(s)),
+        );
+        senders.extend(
+            self.metadata_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::Metadata(s)),
+        );
+        senders.extend(
+            self.profiler_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::Profiler(s)),
+        );
+        senders.extend(
+            self.security_context_proxies
+                .drain()
+                .map(|(_, s)| ProxySender::SecurityContext(s)),
+        );
+        (self.core_proxy.take(), senders)
+    }
+}
+
+type PendingSyncSender = tokio::sync::oneshot::Sender<Result<(), error::ServerError>>;
+
+// Evaluate $body with $sender bound to the event channel of the proxy, whatever its event type
+macro_rules! with_sender {
+    ($proxy_sender:expr, $sender:ident => $body:expr) => {
+        match $proxy_sender {
+            ProxySender::Client($sender) => $body,
+            ProxySender::Registry($sender) => $body,
+            ProxySender::Device($sender) => $body,
+            ProxySender::Factory($sender) => $body,
+            ProxySender::Link($sender) => $body,
+            ProxySender::Module($sender) => $body,
+            ProxySender::Node($sender) => $body,
+            ProxySender::Port($sender) => $body,
+            ProxySender::ClientNode($sender) => $body,
+            ProxySender::Metadata($sender) => $body,
+            ProxySender::Profiler($sender) => $body,
+            ProxySender::SecurityContext($sender) => $body,
+        }
+    };
+}
+
+// The event channel of a single proxy, for routing messages by id
+enum ProxySender {
+    Client(queue::EventSender<ClientEvent>),
+    Registry(queue::EventSender<RegistryEvent>),
+    Device(queue::EventSender<DeviceEvent>),
+    Factory(queue::EventSender<FactoryEvent>),
+    Link(queue::EventSender<LinkEvent>),
+    Module(queue::EventSender<ModuleEvent>),
+    Node(queue::EventSender<NodeEvent>),
+    Port(queue::EventSender<PortEvent>),
+    ClientNode(queue::EventSender<ClientNodeEvent>),
+    Metadata(queue::EventSender<MetadataEvent>),
+    Profiler(queue::EventSender<ProfilerEvent>),
+    SecurityContext(queue::EventSender<SecurityContextEvent>),
+}
+
+impl ProxySender {
+    // The interface name used in logs
+    #[cfg(feature = "tracing")]
+    fn interface(&self) -> &'static str {
+        match self {
+            ProxySender::Client(_) => "Client",
+            ProxySender::Registry(_) => "Registry",
+            ProxySender::Device(_) => "Device",
+            ProxySender::Factory(_) => "Factory",
+            ProxySender::Link(_) => "Link",
+            ProxySender::Module(_) => "Module",
+            ProxySender::Node(_) => "Node",
+            ProxySender::Port(_) => "Port",
+            ProxySender::ClientNode(_) => "ClientNode",
+            ProxySender::Metadata(_) => "Metadata",
+            ProxySender::Profiler(_) => "Profiler",
+            ProxySender::SecurityContext(_) => "SecurityContext",
+        }
+    }
+
+    // Send a Done event that was received on the core to the proxy it belongs to
+    fn send_done(&self, done: Done) {
+        with_sender!(self, sender => sender.send(ProxyEvent::done(done)));
+    }
+
+    // Send an Error event that was received on the core to the proxy it belongs to
+    fn send_error(&self, error: error::ServerError) {
+        with_sender!(self, sender => sender.send(ProxyEvent::error(error)));
+    }
+
+    // Send the final Disconnected event, the channel closes when the sender is dropped afterwards
+    fn send_disconnected(self, reason: error::DisconnectReason) {
+        with_sender!(self, sender => sender.send(ProxyEvent::disconnected(reason)));
+    }
+
+    // Tell the proxy the connection was lost and is being reestablished
+    fn send_connection_lost(&self, reason: error::DisconnectReason) {
+        with_sender!(self, sender => sender.send(ProxyEvent::connection_lost(reason)));
+    }
+
+    // Tell the proxy it was restored on a new connection
+    fn send_reconnected(&self) {
+        with_sender!(self, sender => sender.send(ProxyEvent::reconnected()));
+    }
 }
 
 impl Default for Proxies {
     fn default() -> Self {
         Self {
             id_counter: 1, // Core and Client Proxies already have id 0 and 1
+            free_ids: Default::default(),
+            bound_ids: Default::default(),
+            pending_syncs: Default::default(),
+            bindings: Default::default(),
+            generation: Default::default(),
+            removed_globals: Default::default(),
+            capacities: Default::default(),
             core_proxy: Default::default(),
-            client_proxy: Default::default(),
+            client_proxies: Default::default(),
             registry_proxies: Default::default(),
             device_proxies: Default::default(),
             factory_proxies: Default::default(),
@@ -96,99 +469,373 @@ impl Default for Proxies {
             client_node_proxies: Default::default(),
             metadata_proxies: Default::default(),
             profiler_proxies: Default::default(),
+            security_context_proxies: Default::default(),
         }
     }
 }
 
+#[derive(Clone)]
 pub(crate) struct PipewireReaderHandle {
     sender: tokio::sync::mpsc::Sender<PipewireReaderMessage>,
 }
 
 impl PipewireReaderHandle {
-    pub fn new(stream: tokio::net::unix::OwnedReadHalf, proxies: Arc<Mutex<Proxies>>) -> Self {
+    pub fn new(
+        stream: tokio::net::unix::OwnedReadHalf,
+        proxies: Arc<Mutex<Proxies>>,
+        writer: Arc<Mutex<PipewireWriter>>,
+        reconnect: Option<reconnect::Reconnect>,
+        tap: Option<tap::SharedTap>,
+        recorder: Option<record::SharedRecorder>,
+    ) -> Self {
         let (sender, receiver) = tokio::sync::mpsc::channel(8);
-        let reader = PipewireReader::new(stream, receiver, proxies);
+        let reader =
+            PipewireReader::new(stream, receiver, proxies, writer, reconnect, tap, recorder);
         tokio::spawn(run_reader(reader));
         Self { sender }
     }
 }
 
+// Wait for PipewireConnection::close, never returns once every handle is dropped.
+// The connection then lives on until the socket closes
+async fn wait_for_close(
+    control: &mut tokio::sync::mpsc::Receiver<PipewireReaderMessage>,
+) -> tokio::sync::oneshot::Sender<()> {
+    match control.recv().await {
+        Some(PipewireReaderMessage::Close(done)) => done,
+        None => std::future::pending().await,
+    }
+}
+
 async fn run_reader(mut reader: PipewireReader) {
-    while let Some(msg) = reader.stream.next().await {
-        match msg {
-            Ok(msg) => match reader.handle_message_frame(msg).await {
-                Err(e) => {
-                    dbg!(e);
+    let mut closed = None;
+    let reason = loop {
+        let reason = tokio::select! {
+            message = reader.stream.read_message() => match message {
+                Ok(Some(message)) => {
+                    reader.observe(&message);
+                    match reader.handle_message(message).await {
+                        Ok(()) => continue,
+                        Err(e) => error::DisconnectReason::Error(Arc::new(e)),
+                    }
                 }
-                Ok(_) => (),
+                Ok(None) => error::DisconnectReason::ServerClosed,
+                Err(e) => error::DisconnectReason::Error(Arc::new(e)),
             },
-            Err(e) => {
-                println!("Error {}", e);
+            done = wait_for_close(&mut reader.control) => {
+                closed = Some(done);
+                break error::DisconnectReason::Closed;
             }
+        };
+        let Some(settings) = reader.reconnect.clone() else {
+            break reason;
+        };
+        #[cfg(feature = "tracing")]
+        tracing::info!(%reason, "Connection lost, reconnecting");
+        match reconnect::reconnect(&mut reader, &settings, reason).await {
+            Ok(()) => (),
+            Err(reconnect::Stopped::Closed(done)) => {
+                closed = Some(done);
+                break error::DisconnectReason::Closed;
+            }
+            Err(reconnect::Stopped::GaveUp(reason)) => break reason,
         }
+    };
+    #[cfg(feature = "tracing")]
+    tracing::info!(%reason, "Connection stopped");
+    let (core, senders) = reader.proxies.lock().await.disconnect();
+    if let Some(core) = core {
+        core.send(CoreEvent::Disconnected(reason.clone()));
+    }
+    for sender in senders {
+        sender.send_disconnected(reason.clone());
+    }
+    if let Some(closed) = closed {
+        let _ = closed.send(());
     }
 }
 
 impl PipewireConnection {
-    pub async fn connect(stream: tokio::net::UnixStream) -> io::Result<(core_proxy::CoreProxy, client::ClientProxy)> {
-        let (input_stream, output_stream) = stream.into_split();
-        let proxies = Arc::new(Mutex::new(Proxies::default()));
-        let reader = PipewireReaderHandle::new(input_stream, proxies.clone());
-        let writer = PipewireWriter::new(output_stream);
+    // Connect to the remote in PIPEWIRE_REMOTE, or pipewire-0
+    pub async fn connect_default() -> io::Result<(core_proxy::CoreProxy, client::ClientProxy)> {
+        builder::ConnectionBuilder::new().connect().await
+    }
 
+    // Connect to a socket name, an absolute path or a list like "[pipewire-0-manager,pipewire-0]"
+    pub async fn connect_remote(
+        remote: &str,
+    ) -> io::Result<(core_proxy::CoreProxy, client::ClientProxy)> {
+        builder::ConnectionBuilder::new()
+            .remote(remote)
+            .connect()
+            .await
+    }
+
+    // Connect on an already connected socket with the default configuration
+    pub async fn connect(
+        stream: tokio::net::UnixStream,
+    ) -> io::Result<(core_proxy::CoreProxy, client::ClientProxy)> {
+        builder::ConnectionBuilder::new()
+            .connect_stream(stream)
+            .await
+    }
+
+    // Stop the connection. Every proxy receives a Disconnected event as its last event,
+    // pending syncs fail and later method calls return errors
+    pub async fn close(&self) -> io::Result<()> {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        // If the reader already stopped, the proxies was told why then
+        if self
+            .reader
+            .sender
+            .send(PipewireReaderMessage::Close(sender))
+            .await
+            .is_ok()
+        {
+            let _ = receiver.await;
+        }
+        self.writer.lock().await.shutdown().await
+    }
+
+    pub fn builder() -> builder::ConnectionBuilder {
+        builder::ConnectionBuilder::new()
+    }
+
+    pub(crate) async fn start(
+        stream: tokio::net::UnixStream,
+        capacities: builder::ChannelCapacities,
+        version: i32,
+        properties: HashMap<String, String>,
+        reconnect: Option<reconnect::Reconnect>,
+        tap: Option<tap::SharedTap>,
+        recorder: Option<record::SharedRecorder>,
+    ) -> io::Result<(core_proxy::CoreProxy, client::ClientProxy)> {
+        let (input_stream, output_stream) = stream.into_split();
+        let proxies = Arc::new(Mutex::new(Proxies {
+            capacities,
+            ..Default::default()
+        }));
+        let writer = PipewireWriter::new(output_stream, tap.clone(), recorder.clone());
         let writer = Arc::new(Mutex::new(writer));
+        let reader = PipewireReaderHandle::new(
+            input_stream,
+            proxies.clone(),
+            writer.clone(),
+            reconnect,
+            tap,
+            recorder,
+        );
         let mut connection = PipewireConnection {
-            writer: writer,
-            reader: reader,
-            proxies: proxies,
+            writer,
+            reader,
+            proxies,
         };
-        let core = connection.create_core_proxy().await?;
-        let props = HashMap::from([("application.name".into(), "pipewirers".into())]); // TODO: Add properties as argument
-        let client = connection.create_client_proxy(props).await?;
-        Ok((core,client ))
+        let core = connection.create_core_proxy(version).await?;
+        let client = connection.create_client_proxy(properties).await?;
+        Ok((core, client))
     }
 
-
-    pub async fn create_core_proxy(&mut self) -> io::Result<core_proxy::CoreProxy> {
-        let (sender, receiver) = tokio::sync::mpsc::channel(8);
-        self.proxies.lock().await.core_proxy = Some(sender);
-        core_proxy::CoreProxy::new(self.writer.clone(), receiver, self.proxies.clone()).await
+    pub async fn create_core_proxy(&mut self, version: i32) -> io::Result<core_proxy::CoreProxy> {
+        let receiver = {
+            let mut proxies = self.proxies.lock().await;
+            let (sender, receiver) = tokio::sync::mpsc::channel(proxies.capacities.core);
+            proxies.core_proxy = Some(queue::EventSender::new(sender));
+            receiver
+        };
+        core_proxy::CoreProxy::new(
+            self.writer.clone(),
+            receiver,
+            self.proxies.clone(),
+            self.reader.clone(),
+            version,
+        )
+        .await
     }
 
-    pub async fn create_client_proxy(&mut self, properties: HashMap<String, String>) -> io::Result<client::ClientProxy> {
-        let (sender, receiver) = tokio::sync::mpsc::channel(8);
-        self.proxies.lock().await.client_proxy = Some(sender);
-        client::ClientProxy::new(self.writer.clone(), receiver, properties).await
+    pub async fn create_client_proxy(
+        &mut self,
+        properties: HashMap<String, String>,
+    ) -> io::Result<client::ClientProxy> {
+        let receiver = {
+            let mut proxies = self.proxies.lock().await;
+            let (sender, receiver) = tokio::sync::mpsc::channel(proxies.capacities.client);
+            proxies
+                .client_proxies
+                .insert(client::ClientProxy::CLIENT_ID, queue::EventSender::new(sender));
+            receiver
+        };
+        client::ClientProxy::new(
+            self.writer.clone(),
+            receiver,
+            self.proxies.clone(),
+            properties,
+        )
+        .await
     }
 }
 impl PipewireWriter {
+    // Shut down the writing side, the server sees the connection close
+    async fn shutdown(&mut self) -> io::Result<()> {
+        match tokio::io::AsyncWriteExt::shutdown(&mut self.stream).await {
+            Err(e) if e.kind() == io::ErrorKind::NotConnected => Ok(()), // Already gone
+            result => result,
+        }
+    }
+
     async fn call_method(
         &mut self,
         id: i32,
         opcode: u32,
         payload: impl PodSerialize,
+    ) -> io::Result<()> {
+        self.call_method_with_fds(id, opcode, payload, &[]).await
+    }
+
+    // Call a method followed by a sync on the same proxy.
+    // The returned PendingSync fails with the ServerError if the server reported an error for the call.
+    // It must be awaited after the connection lock is released, the reader may need the lock to make progress
+    async fn call_method_checked(
+        &mut self,
+        proxies: &Mutex<Proxies>,
+        id: i32,
+        opcode: u32,
+        payload: impl PodSerialize,
+    ) -> io::Result<proxy::PendingSync> {
+        self.call_method_checked_with_fds(proxies, id, opcode, payload, &[]).await
+    }
+
+    // Like call_method_checked, passing file descriptors along with the method
+    async fn call_method_checked_with_fds(
+        &mut self,
+        proxies: &Mutex<Proxies>,
+        id: i32,
+        opcode: u32,
+        payload: impl PodSerialize,
+        fds: &[BorrowedFd<'_>],
+    ) -> io::Result<proxy::PendingSync> {
+        self.call_method_with_fds(id, opcode, payload, fds).await?;
+        self.sync(proxies, id).await
+    }
+
+    // Call a method that creates the object with new_id, followed by a sync for the new object.
+    // The server reports the errors of the call on new_id, so only this call fails with them
+    async fn call_constructor_checked(
+        &mut self,
+        proxies: &Mutex<Proxies>,
+        id: i32,
+        opcode: u32,
+        payload: impl PodSerialize,
+        new_id: i32,
+    ) -> io::Result<proxy::PendingSync> {
+        self.call_method(id, opcode, payload).await?;
+        self.sync(proxies, new_id).await
+    }
+
+    // Call a method passing file descriptors along with the message.
+    // spa::value::Fd values in the payload are indices into fds
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "method",
+            level = "debug",
+            skip_all,
+            fields(id, opcode, seq = self.seq, method = trace::type_name::<P>(), n_fds = fds.len())
+        )
+    )]
+    async fn call_method_with_fds<P: PodSerialize>(
+        &mut self,
+        id: i32,
+        opcode: u32,
+        payload: P,
+        fds: &[BorrowedFd<'_>],
     ) -> io::Result<()> {
         let mut message = Message::new(id, opcode, self.seq, payload);
-        self.stream.writable().await?;
-        self.write(&mut message).await?;
+        message.header.n_fds = fds.len() as u32;
+        self.write(&mut message, fds).await?;
         self.seq += 1;
         Ok(())
     }
 
-    async fn write<T: PodSerialize>(&mut self, message: &mut Message<T>) -> io::Result<()> {
-        let buffer: Vec<u8> = PodSerializer::serialize(Cursor::new(Vec::new()), &message.payload)
-            .unwrap()
-            .0
-            .into_inner();
+    // Send a core sync for the proxy with id.
+    // The returned PendingSync resolves when the done event with the same id and seq is received
+    async fn sync(&mut self, proxies: &Mutex<Proxies>, id: i32) -> io::Result<proxy::PendingSync> {
+        let seq = self.seq + 1;
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        proxies.lock().await.pending_syncs.insert((id, seq), sender);
+        let result = self
+            .call_method(
+                core_proxy::CORE_ID,
+                <core_proxy::Sync as spa::opcode::MessageOpCode>::OP_CODE,
+                core_proxy::Sync { id, seq },
+            )
+            .await;
+        if let Err(e) = result {
+            proxies.lock().await.pending_syncs.remove(&(id, seq));
+            return Err(e);
+        }
+        Ok(proxy::PendingSync::new(seq, receiver))
+    }
+
+    async fn write<T: PodSerialize>(
+        &mut self,
+        message: &mut Message<T>,
+        fds: &[BorrowedFd<'_>],
+    ) -> io::Result<()> {
+        let mut buffer: Vec<u8> =
+            PodSerializer::serialize(Cursor::new(Vec::new()), &message.payload)
+                .unwrap()
+                .0
+                .into_inner();
+        let generation = self.next_generation.take().unwrap_or(self.recv_generation);
+        if generation != self.sent_generation {
+            buffer.extend_from_slice(&footer::client_generation(generation));
+            self.sent_generation = generation;
+        }
         message.header.opcode_size += buffer.len() as u32;
-        self.stream.write_all(message.header.as_bytes()).await?;
-        self.stream.write_all(&buffer).await
+        let mut bytes = Vec::with_capacity(message.header.as_bytes().len() + buffer.len());
+        bytes.extend_from_slice(message.header.as_bytes());
+        bytes.extend_from_slice(&buffer);
+        #[cfg(feature = "tracing")]
+        {
+            tracing::debug!(size = buffer.len(), "Sending message");
+            trace::dump_pod(&buffer);
+        }
+        if let Some(tap) = &self.tap {
+            tap.lock()
+                .unwrap()
+                .message(record::Direction::Send, &message.header, &buffer);
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder
+                .lock()
+                .unwrap()
+                .message(record::Direction::Send, &message.header, &buffer);
+        }
+        socket::write_message(&self.stream, &bytes, fds).await
     }
 
-    fn new(output_stream: tokio::net::unix::OwnedWriteHalf) -> Self {
+    // Continue on a new connection, used when reconnecting
+    fn replace_stream(&mut self, output_stream: tokio::net::unix::OwnedWriteHalf) {
+        self.stream = output_stream;
+        self.seq = 0;
+        self.recv_generation = 0;
+        self.sent_generation = 0;
+        self.next_generation = None;
+    }
+
+    fn new(
+        output_stream: tokio::net::unix::OwnedWriteHalf,
+        tap: Option<tap::SharedTap>,
+        recorder: Option<record::SharedRecorder>,
+    ) -> Self {
         Self {
             stream: output_stream,
             seq: 0,
+            tap,
+            recorder,
+            recv_generation: 0,
+            sent_generation: 0,
+            next_generation: None,
         }
     }
 }
@@ -198,140 +845,322 @@ impl PipewireReader {
         input_stream: tokio::net::unix::OwnedReadHalf,
         control: tokio::sync::mpsc::Receiver<PipewireReaderMessage>,
         proxies: Arc<Mutex<Proxies>>,
+        writer: Arc<Mutex<PipewireWriter>>,
+        reconnect: Option<reconnect::Reconnect>,
+        tap: Option<tap::SharedTap>,
+        recorder: Option<record::SharedRecorder>,
     ) -> Self {
-        let reader: FramedRead<tokio::net::unix::OwnedReadHalf, LengthDelimitedCodec> =
-            LengthDelimitedCodec::builder()
-                .length_field_offset(4)
-                .length_field_length(3)
-                .num_skip(0)
-                .length_adjustment(16)
-                .native_endian()
-                .new_read(input_stream);
         PipewireReader {
-            stream: reader,
+            stream: socket::SocketReader::new(input_stream),
             control,
             proxies,
+            writer,
+            reconnect,
+            tap,
+            recorder,
         }
     }
 
+    // Pass a received message on to the tap and the recorder. Their output is written while
+    // holding a std Mutex, a blocking write here holds up the reader task
+    fn observe(&self, message: &socket::ReceivedMessage) {
+        if let Some(tap) = &self.tap {
+            tap.lock().unwrap().message(
+                record::Direction::Receive,
+                &message.header,
+                &message.payload,
+            );
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.lock().unwrap().message(
+                record::Direction::Receive,
+                &message.header,
+                &message.payload,
+            );
+        }
+    }
+
+    // Track the generation in the footer of a message, returns the payload without the footer.
+    // Done before the event is passed on, so a bind in response to it sends the new generation
+    async fn handle_footer<'a>(&self, payload: &'a [u8]) -> &'a [u8] {
+        let (payload, footer) = footer::split(payload);
+        let footers = footer::parse(footer);
+        if let Some(generation) = footer::generation(&footers, footer::FOOTER_CORE_GENERATION) {
+            self.writer.lock().await.recv_generation = generation;
+            self.proxies.lock().await.generation = generation;
+        }
+        payload
+    }
+
+    // Pass a received message on to its proxy. Only failing to write to the socket, when
+    // answering a ping, is returned as the connection is broken then. A message that does not
+    // decode or is for an id without a proxy is skipped and reported to the core as a
+    // SkippedMessage event, so it does not stop the connection
+    async fn handle_message(&self, message: socket::ReceivedMessage) -> std::io::Result<()> {
+        let (id, opcode) = (message.header.id, message.header.opcode());
+        let error = match self
+            .handle_message_frame(message.header, message.payload, message.fds)
+            .await
+        {
+            Ok(()) => return Ok(()),
+            Err(error::PipewireConnectionError::IoError(e)) => return Err(e),
+            Err(error::PipewireConnectionError::ProxyNotPresentError(_)) => {
+                error::MessageError::UnknownProxy { id, opcode }
+            }
+            Err(_) => error::MessageError::Invalid { id, opcode },
+        };
+        let core_proxy = self.proxies.lock().await.core_proxy.clone();
+        if let Some(core_proxy) = core_proxy {
+            core_proxy.send(CoreEvent::SkippedMessage(error));
+        }
+        Ok(())
+    }
+
+    // Errors are logged by the span when the tracing feature is enabled
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "message",
+            level = "debug",
+            skip_all,
+            err(level = "warn"),
+            fields(
+                id = header.id,
+                opcode = header.opcode(),
+                seq = header.seq,
+                size = header.size(),
+                n_fds = fds.len(),
+                interface = tracing::field::Empty,
+            )
+        )
+    )]
     async fn handle_message_frame(
         &self,
-        bytes: BytesMut,
+        header: Header,
+        message_bytes: BytesMut,
+        fds: Vec<OwnedFd>,
     ) -> Result<(), error::PipewireConnectionError> {
-        match bytes.split_first_chunk::<16>() {
-            Some((header_bytes, message_bytes)) => {
-                let header = Header::read_from_bytes(header_bytes)
-                    .expect("Length of byte slice must be equal to header size");
-                match header.id {
-                    core_proxy::CORE_ID => {
-                        let (remain, event) = core_proxy::CoreEvent::deserialize_from_opcode(
-                            header.opcode(),
-                            message_bytes,
-                        )?;
-
-                        // We handle done events in a special way, by sending them to proxies corresponding to the id field inside
-                        // TODO: Not sure this is the best way, and should maybe be handled at another level
-                        if let CoreEvent::Done(done_event) = &event {
-                            self.send_done_to_proxy(done_event).await;
-                        }
-
-                        match &self.proxies.lock().await.core_proxy {
-                            Some(core_proxy) => {
-                                match core_proxy.send(event).await {
-                                    Ok(o) => return Ok(o),
-                                    Err(e) => {
-                                        self.proxies.lock().await.core_proxy = None; // We could not send to core proxy, so remove it
-                                        return Err(
-                                            error::PipewireConnectionError::ProxyNotPresentError(
-                                                header.id,
-                                            ),
-                                        );
-                                    }
-                                };
-                            }
-                            None => Ok(()),
-                        }
+        #[cfg(feature = "tracing")]
+        trace::dump_pod(&message_bytes);
+        let message_bytes = self.handle_footer(&message_bytes).await;
+        let fds = Fds::new(fds);
+        if header.id == core_proxy::CORE_ID {
+            let (_remain, mut event) =
+                core_proxy::CoreEvent::deserialize_from_opcode(header.opcode(), message_bytes)?;
+            #[cfg(feature = "tracing")]
+            {
+                tracing::Span::current().record("interface", "Core");
+                tracing::debug!("Received message");
+            }
+
+            // We handle done events in a special way, by sending them to proxies corresponding to the id field inside
+            // TODO: Not sure this is the best way, and should maybe be handled at another level
+            match &event {
+                CoreEvent::Done(done_event) => {
+                    let pending_sync = self
+                        .proxies
+                        .lock()
+                        .await
+                        .pending_syncs
+                        .remove(&(done_event.id, done_event.seq));
+                    // The done of a sync we sent is consumed by resolving it, so proxies are not
+                    // flooded with done events nobody reads
+                    if let Some(pending_sync) = pending_sync {
+                        let _ = pending_sync.send(Ok(()));
+                        return Ok(());
                     }
-                    client::ClientProxy::CLIENT_ID => {
-                        let (remain, event) = client::ClientEvent::deserialize_from_opcode(
-                            header.opcode(),
-                            message_bytes,
-                        )?;
-                        match &self.proxies.lock().await.client_proxy {
-                            Some(client_proxy) => {
-                                match client_proxy.send(event).await {
-                                    Ok(o) => return Ok(o),
-                                    Err(e) => {
-                                        self.proxies.lock().await.client_proxy = None; // We could not send to client proxy, so remove it
-                                        return Err(
-                                            error::PipewireConnectionError::ProxyNotPresentError(
-                                                header.id,
-                                            ),
-                                        );
-                                    }
-                                };
-                            }
-                            None => Ok(()),
-                        }
+                    // Done events for other proxies are not sent to the core proxy as well,
+                    // so it does not fill up when the core proxy events are not read
+                    if self.send_done_to_proxy(done_event).await {
+                        return Ok(());
                     }
-                    id => {
-                        if self.proxies.lock().await.registry_proxies.contains_key(&id) {
-                            let (remain, event) = registry::RegistryEvent::deserialize_from_opcode(
-                                header.opcode(),
-                                message_bytes,
-                            )?;
-
-                            let mut registry_proxies =
-                                self.proxies.lock().await.registry_proxies.clone();
-                            let registry_proxy = registry_proxies.get(&id).unwrap();
-                            match registry_proxy.send(event).await {
-                                Ok(o) => return Ok(o),
-                                Err(e) => {
-                                    registry_proxies.remove(&id); // We could not send to proxy, so remove it
-                                    return Err(
-                                        error::PipewireConnectionError::ProxyNotPresentError(
-                                            header.id,
-                                        ),
-                                    );
-                                }
-                            };
-                        } else {
-                            println!("Could not find id {} ", id);
-                            return Err(error::PipewireConnectionError::ProxyNotPresentError(
-                                header.id,
-                            ));
-                        }
+                }
+                CoreEvent::Error(error_event) => {
+                    let error = error::ServerError::from(error_event.clone());
+                    self.proxies.lock().await.fail_pending_syncs(&error);
+                    // Like done events, errors for other proxies are only sent to that proxy
+                    if self.send_error_to_proxy(error).await {
+                        return Ok(());
                     }
                 }
+                CoreEvent::Ping(ping) => {
+                    // Answered here, so the server does not consider us hung when the core proxy is not read
+                    self.writer
+                        .lock()
+                        .await
+                        .call_method(
+                            core_proxy::CORE_ID,
+                            <core_proxy::Pong as spa::opcode::MessageOpCode>::OP_CODE,
+                            core_proxy::Pong {
+                                id: ping.id,
+                                seq: ping.seq,
+                            },
+                        )
+                        .await?;
+                    // The ping is still passed on for diagnostics
+                }
+                CoreEvent::RemoveId(remove_id) => self.proxies.lock().await.free_id(remove_id.id),
+                CoreEvent::BoundId(bound_id) => {
+                    self.proxies
+                        .lock()
+                        .await
+                        .bound_ids
+                        .insert(bound_id.id, bound_id.global_id);
+                }
+                _ => (),
             }
-            None => {
-                Err(error::PipewireConnectionError::Unknown) //TODO: Not corret err
+            if let CoreEvent::AddMem(_, event_fds) = &mut event {
+                *event_fds = fds;
             }
+
+            let core_proxy = self.proxies.lock().await.core_proxy.clone();
+            return match core_proxy {
+                Some(core_proxy) => self.send_event(core_proxy, header.id, event).await,
+                None => Ok(()),
+            };
         }
-    }
 
-    async fn send_done_to_proxy(&self, done_event: &Done) {
-        let id = done_event.id;
-        if id == ClientProxy::CLIENT_ID {
-            match &self.proxies.lock().await.client_proxy {
-                Some(client_proxy) => {
-                    let _ = client_proxy
-                        .send(ClientEvent::Done(done_event.clone()))
-                        .await;
+        let sender = self.proxies.lock().await.sender(header.id);
+        #[cfg(feature = "tracing")]
+        if let Some(sender) = &sender {
+            tracing::Span::current().record("interface", sender.interface());
+            tracing::debug!("Received message");
+        }
+        match sender {
+            Some(ProxySender::Client(sender)) => {
+                self.dispatch(sender, &header, message_bytes).await
+            }
+            Some(ProxySender::Registry(sender)) => {
+                let (_remain, mut event) =
+                    RegistryEvent::deserialize_from_opcode(header.opcode(), message_bytes)?;
+                self.proxies.lock().await.track_global(&mut event);
+                self.send_event(sender, header.id, event).await
+            }
+            Some(ProxySender::Device(sender)) => {
+                self.dispatch(sender, &header, message_bytes).await
+            }
+            Some(ProxySender::Factory(sender)) => {
+                self.dispatch(sender, &header, message_bytes).await
+            }
+            Some(ProxySender::Link(sender)) => self.dispatch(sender, &header, message_bytes).await,
+            Some(ProxySender::Module(sender)) => {
+                self.dispatch(sender, &header, message_bytes).await
+            }
+            Some(ProxySender::Node(sender)) => self.dispatch(sender, &header, message_bytes).await,
+            Some(ProxySender::Port(sender)) => self.dispatch(sender, &header, message_bytes).await,
+            Some(ProxySender::ClientNode(sender)) => {
+                let (_remain, mut event) =
+                    ClientNodeEvent::deserialize_from_opcode(header.opcode(), message_bytes)?;
+                match &mut event {
+                    ClientNodeEvent::Transport(_, event_fds)
+                    | ClientNodeEvent::SetActivation(_, event_fds) => *event_fds = fds,
+                    _ => (),
                 }
-                None => (),
+                self.send_event(sender, header.id, event).await
+            }
+            Some(ProxySender::Metadata(sender)) => {
+                self.dispatch(sender, &header, message_bytes).await
+            }
+            Some(ProxySender::Profiler(sender)) => {
+                self.dispatch(sender, &header, message_bytes).await
+            }
+            Some(ProxySender::SecurityContext(sender)) => {
+                self.dispatch(sender, &header, message_bytes).await
+            }
+            None => Err(error::PipewireConnectionError::ProxyNotPresentError(
+                header.id,
+            )),
+        }
+    }
+
+    // Deserialize the event in message_bytes and send it to the proxy
+    async fn dispatch<E: DeserializeFromOpCode + Send + 'static>(
+        &self,
+        sender: queue::EventSender<E>,
+        header: &Header,
+        message_bytes: &[u8],
+    ) -> Result<(), error::PipewireConnectionError> {
+        let (_remain, event) = E::deserialize_from_opcode(header.opcode(), message_bytes)?;
+        self.send_event(sender, header.id, event).await
+    }
+
+    // Never waits for the proxy to make room, see queue::EventSender
+    async fn send_event<E: Send + 'static>(
+        &self,
+        sender: queue::EventSender<E>,
+        id: i32,
+        event: E,
+    ) -> Result<(), error::PipewireConnectionError> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            event = trace::type_name::<E>(),
+            "Dispatching event to proxy"
+        );
+        // The application dropped the proxy, so nobody is waiting for its events
+        if !sender.send(event) {
+            self.proxies.lock().await.remove(id);
+        }
+        Ok(())
+    }
+
+    // Returns false if there is no proxy with the id of the error, other than the core
+    async fn send_error_to_proxy(&self, error: error::ServerError) -> bool {
+        let sender = self.proxies.lock().await.sender(error.id);
+        match sender {
+            Some(sender) => {
+                sender.send_error(error);
+                true
             }
+            None => false,
         }
-        if self.proxies.lock().await.registry_proxies.contains_key(&id) {
-            let mut registry_proxies = self.proxies.lock().await.registry_proxies.clone();
-            let registry_proxy = registry_proxies.get(&id).unwrap();
-            let _ = registry_proxy
-                .send(RegistryEvent::Done(done_event.clone()))
-                .await;
+    }
+
+    // Returns false if there is no proxy with the id of the done event
+    async fn send_done_to_proxy(&self, done_event: &Done) -> bool {
+        let sender = self.proxies.lock().await.sender(done_event.id);
+        match sender {
+            Some(sender) => {
+                sender.send_done(done_event.clone());
+                true
+            }
+            None => false,
         }
     }
 }
 
+// File descriptors received together with a message.
+// spa::value::Fd values in the message payload are indices into this list.
+#[derive(Debug, Default, Clone)]
+pub struct Fds(Vec<Option<Arc<OwnedFd>>>);
+
+impl Fds {
+    fn new(fds: Vec<OwnedFd>) -> Self {
+        Self(fds.into_iter().map(|fd| Some(Arc::new(fd))).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Borrow the file descriptor that the index in fd refers to
+    pub fn get(&self, fd: spa::value::Fd) -> Option<BorrowedFd<'_>> {
+        let index = usize::try_from(fd.0).ok()?;
+        self.0.get(index)?.as_ref().map(|fd| fd.as_fd())
+    }
+
+    // Take ownership of the file descriptor that the index in fd refers to.
+    // If the event holding the fds was cloned, the file descriptor is duplicated instead
+    pub fn take(&mut self, fd: spa::value::Fd) -> Option<OwnedFd> {
+        let index = usize::try_from(fd.0).ok()?;
+        let fd = self.0.get_mut(index)?.take()?;
+        Arc::try_unwrap(fd).or_else(|fd| fd.try_clone()).ok()
+    }
+}
+
 #[derive(IntoBytes, FromBytes, Immutable, Debug)]
 #[repr(C)]
 struct Header {
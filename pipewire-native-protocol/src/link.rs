@@ -1,16 +1,32 @@
 use std::collections::HashMap;
 
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+
+use crate::proxy;
+
+proxy::define_proxy! {
+    pub struct LinkProxy: LinkEvent {}
+    bindable("PipeWire:Interface:Link", 3, link, link_proxies)
+}
+
 // === Methods ===
 
 // Link has no methods
 
 // === Events ===
-pub enum LinkEvent {
-    Info(Info)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum LinkEvent {
+        Info(Info),
+    }
 }
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(0)]
 pub struct Info {
     pub id: i32,
     pub output_node_id: i32,
@@ -18,8 +34,26 @@ pub struct Info {
     pub input_node_id: i32,
     pub input_port_id: i32,
     pub change_mask: i64,
-    pub state: i32,
-    pub error: String,
+    pub state: i32, // pw_link_state, error is -2 and active is 4
+    pub error: Option<String>,
     pub format: spa::value::Value,
     pub props: HashMap<String, String>,
 }
+
+impl opcode::DeserializeFromOpCode for LinkEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Info::OP_CODE => {
+                let (remain, value): (&[u8], Info) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, LinkEvent::Info(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
+}
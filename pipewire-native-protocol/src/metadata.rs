@@ -1,16 +1,148 @@
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
+use std::collections::HashMap;
+
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+use tokio::io;
+
+use crate::proxy;
+
+proxy::define_proxy! {
+    pub struct MetadataProxy: MetadataEvent {
+        properties: HashMap<(i32, String), (Option<String>, String)>, // (subject, key) -> (type, value)
+    }
+    on_event(apply_event)
+    bindable("PipeWire:Interface:Metadata", 3, metadata, metadata_proxies)
+}
+
+impl MetadataProxy {
+    // Set the property key of subject, a value of None removes the property
+    pub async fn set_property(
+        &self,
+        subject: i32,
+        key: &str,
+        type_: Option<&str>,
+        value: Option<&str>,
+    ) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(
+                &self.proxies,
+                self.id,
+                SetProperty::OP_CODE,
+                SetProperty {
+                    subject,
+                    key: Some(key.to_string()),
+                    type_: type_.map(str::to_string),
+                    value: value.map(str::to_string),
+                },
+            )
+            .await?;
+        pending_sync.await
+    }
+
+    // Remove all properties of subject
+    pub async fn clear_subject(&self, subject: i32) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(
+                &self.proxies,
+                self.id,
+                SetProperty::OP_CODE,
+                SetProperty {
+                    subject,
+                    key: None,
+                    type_: None,
+                    value: None,
+                },
+            )
+            .await?;
+        pending_sync.await
+    }
+
+    // Remove all properties
+    pub async fn clear(&self) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(&self.proxies, self.id, Clear::OP_CODE, Clear::default())
+            .await?;
+        pending_sync.await
+    }
+
+    // Wait until all properties that the server has sent so far are received,
+    // after binding this gives the complete set of properties
+    pub async fn wait_for_properties(&mut self) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .sync(&self.proxies, self.id)
+            .await?;
+        self.receive_until(pending_sync, Some).await
+    }
+
+    // All properties, keyed by (subject, key) with (type, value) as values
+    pub fn properties(&self) -> &HashMap<(i32, String), (Option<String>, String)> {
+        &self.properties
+    }
+
+    // The type and value of the property key on subject
+    pub fn get(&self, subject: i32, key: &str) -> Option<(Option<&str>, &str)> {
+        self.properties
+            .get(&(subject, key.to_string()))
+            .map(|(type_, value)| (type_.as_deref(), value.as_str()))
+    }
+
+    // Events are applied to the local view as they are taken from the channel
+    fn apply_event(&mut self, event: &MetadataEvent) {
+        match event {
+            MetadataEvent::Property(property) => self.apply(property),
+            // The server sends all properties again after the proxy is restored
+            MetadataEvent::Reconnected => self.properties.clear(),
+            _ => (),
+        }
+    }
+
+    fn apply(&mut self, property: &Property) {
+        match (&property.key, &property.value) {
+            (None, _) => self
+                .properties
+                .retain(|(subject, _), _| *subject != property.subject),
+            (Some(key), None) => {
+                self.properties.remove(&(property.subject, key.clone()));
+            }
+            (Some(key), Some(value)) => {
+                self.properties.insert(
+                    (property.subject, key.clone()),
+                    (property.type_.clone(), value.clone()),
+                );
+            }
+        }
+    }
+}
+
 // === Methods ===
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(1)]
 pub struct SetProperty {
     pub subject: i32,
-    pub key: String,
-    pub type_: String,
-    pub value: String,
+    pub key: Option<String>,
+    pub type_: Option<String>,
+    pub value: Option<String>,
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(2)]
 pub struct Clear {
     none: spa::value::Value, // Should always be spa::value::Value::None
 }
@@ -23,14 +155,36 @@ impl Default for Clear {
 }
 
 // === Events ===
-pub enum MetadataEvent {
-    Property(Property)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum MetadataEvent {
+        Property(Property),
+    }
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(0)]
 pub struct Property {
     pub subject: i32,
-    pub key: String,
-    pub type_: String,
-    pub value: String,
+    pub key: Option<String>,
+    pub type_: Option<String>,
+    pub value: Option<String>,
+}
+
+impl opcode::DeserializeFromOpCode for MetadataEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Property::OP_CODE => {
+                let (remain, value): (&[u8], Property) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, MetadataEvent::Property(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
 }
@@ -0,0 +1,429 @@
+// A scriptable server side of the native protocol for tests, running on one end of a socket pair.
+// It does what the daemon does for the core: Sync is answered with Done, registries list the
+// configured globals and binds and destroys are confirmed. Creating an object fails unless a
+// factory global with its factory.name exists. Everything else is up to the test,
+// which sees every method the client sends and can send any event to the client.
+// Like the daemon, every added global gets a new generation, which is sent in a footer. Binding a
+// global newer than the generation the client has seen fails. Permissions the client updates are
+// kept and listed when it asks for them. The params added to a global are enumerated to the
+// proxies bound to it.
+use std::{
+    collections::HashMap,
+    os::fd::{BorrowedFd, OwnedFd},
+    sync::Arc,
+    time::Duration,
+};
+
+use spa::{
+    deserialize::PodDeserialize,
+    opcode::MessageOpCode,
+    serialize::PodSerialize,
+    value::{Id, Value},
+};
+use tokio::{
+    io,
+    net::{UnixListener, UnixStream},
+    sync::{mpsc, Mutex},
+};
+
+use crate::{
+    client::{self, ClientProxy},
+    core_proxy::{self, BoundId, CoreProxy, Done, ErrorEvent, RemoveId, CORE_ID},
+    footer::{self, Footer, FOOTER_CLIENT_GENERATION},
+    param,
+    permissions::{PermissionList, Permissions},
+    registry::{self, Global, GlobalRemove, RegistryEvent, RegistryProxy},
+    socket, PipewireConnection, PipewireWriter,
+};
+
+// How long wait_for_method waits before failing the test
+const METHOD_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A method call received from the client
+#[derive(Debug)]
+pub struct Method {
+    pub id: i32,
+    pub opcode: u32,
+    pub seq: i32,
+    pub payload: Vec<u8>,
+    pub fds: Vec<OwnedFd>,
+}
+
+impl Method {
+    // Whether this is the method M called on the proxy with id
+    pub fn is<M: MessageOpCode>(&self, id: i32) -> bool {
+        self.id == id && self.opcode == M::OP_CODE
+    }
+
+    // The footers after the payload
+    pub fn footers(&self) -> Vec<Footer> {
+        footer::parse(footer::split(&self.payload).1)
+    }
+
+    // The payload as M, None if the opcode is not the one of M or the payload does not decode
+    pub fn decode<'a, M: PodDeserialize<'a> + MessageOpCode>(&'a self) -> Option<M> {
+        if self.opcode != M::OP_CODE {
+            return None;
+        }
+        spa::deserialize::PodDeserializer::deserialize_from(&self.payload)
+            .ok()
+            .map(|(_, method)| method)
+    }
+}
+
+struct ServerState {
+    writer: PipewireWriter, // Events to the client are written as methods are, on the other end
+    globals: Vec<Global>,   // Listed to every registry the client gets
+    registries: Vec<i32>,   // Ids of the registries the client has
+    bound: HashMap<i32, i32>, // Proxy id to the global it is bound to
+    generation: u64,        // Incremented for every global added
+    client_generation: u64, // The generation from the footers of the client
+    permissions: Vec<(i32, Permissions)>, // Of the client, by global id
+    params: Vec<(i32, Id, Value)>, // Global id, param id and the param
+    acknowledge_destroy: bool, // Whether a Destroy is answered with a RemoveId
+}
+
+pub struct MockServer {
+    state: Arc<Mutex<ServerState>>,
+    methods: mpsc::UnboundedReceiver<Method>,
+}
+
+impl MockServer {
+    // Start a server with the globals, the returned stream is the client end of the connection
+    pub fn new(globals: Vec<Global>) -> io::Result<(MockServer, UnixStream)> {
+        let (client, server) = UnixStream::pair()?;
+        Ok((Self::serve(server, globals), client))
+    }
+
+    // Start a server with the globals for the next client connecting to listener. Clients that
+    // connect by remote, like reconnecting ones, need a socket path to connect to
+    pub async fn accept(listener: &UnixListener, globals: Vec<Global>) -> io::Result<MockServer> {
+        let (server, _) = listener.accept().await?;
+        Ok(Self::serve(server, globals))
+    }
+
+    fn serve(server: UnixStream, globals: Vec<Global>) -> MockServer {
+        let (input_stream, output_stream) = server.into_split();
+        let state = Arc::new(Mutex::new(ServerState {
+            writer: PipewireWriter::new(output_stream, None, None),
+            globals,
+            registries: Vec::new(),
+            bound: HashMap::new(),
+            generation: 0,
+            client_generation: 0,
+            permissions: Vec::new(),
+            params: Vec::new(),
+            acknowledge_destroy: true,
+        }));
+        let (sender, methods) = mpsc::unbounded_channel();
+        tokio::spawn(run_server(
+            socket::SocketReader::new(input_stream),
+            state.clone(),
+            sender,
+        ));
+        MockServer { state, methods }
+    }
+
+    // Start a server and connect a client to it with the default configuration
+    pub async fn connect(globals: Vec<Global>) -> io::Result<(MockServer, CoreProxy, ClientProxy)> {
+        let (server, stream) = Self::new(globals)?;
+        let (core, client) = PipewireConnection::connect(stream).await?;
+        Ok((server, core, client))
+    }
+
+    // Send an event to the proxy with id
+    pub async fn send_event<E: PodSerialize + MessageOpCode>(
+        &self,
+        id: i32,
+        event: E,
+    ) -> io::Result<()> {
+        self.send_event_with_fds(id, event, &[]).await
+    }
+
+    // Send an event with file descriptors, spa::value::Fd values in the event are indices into fds
+    pub async fn send_event_with_fds<E: PodSerialize + MessageOpCode>(
+        &self,
+        id: i32,
+        event: E,
+        fds: &[BorrowedFd<'_>],
+    ) -> io::Result<()> {
+        self.state
+            .lock()
+            .await
+            .writer
+            .call_method_with_fds(id, E::OP_CODE, event, fds)
+            .await
+    }
+
+    // Add a global in a new generation, it is announced on every registry the client has
+    pub async fn add_global(&self, mut global: Global) -> io::Result<()> {
+        let mut state = self.state.lock().await;
+        state.generation += 1;
+        global.generation = state.generation;
+        // The core footer has the same layout as the client one, so the writer sends it
+        state.writer.recv_generation = state.generation;
+        for registry in state.registries.clone() {
+            state
+                .writer
+                .call_method(registry, Global::OP_CODE, global.clone())
+                .await?;
+        }
+        state.globals.push(global);
+        Ok(())
+    }
+
+    // Remove a global, the registries of the client get a GlobalRemove for it
+    pub async fn remove_global(&self, id: i32) -> io::Result<()> {
+        let mut state = self.state.lock().await;
+        state.globals.retain(|global| global.id != id);
+        for registry in state.registries.clone() {
+            state
+                .writer
+                .call_method(registry, GlobalRemove::OP_CODE, GlobalRemove { id })
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Add a param with id to the global, it is enumerated after the params added before it
+    pub async fn add_param(&self, global_id: i32, id: Id, param: Value) {
+        self.state.lock().await.params.push((global_id, id, param));
+    }
+
+    // Whether a Destroy is answered with a RemoveId right away, which it is by default. When it is
+    // not, the test sends the RemoveId itself
+    pub async fn acknowledge_destroy(&self, acknowledge: bool) {
+        self.state.lock().await.acknowledge_destroy = acknowledge;
+    }
+
+    // The global the proxy with id was bound to
+    pub async fn bound_global(&self, id: i32) -> Option<i32> {
+        self.state.lock().await.bound.get(&id).copied()
+    }
+
+    // Close the connection, the client sees the server going away
+    pub async fn close(&self) -> io::Result<()> {
+        self.state.lock().await.writer.shutdown().await
+    }
+
+    // The next method the client called, None once the client has closed the connection
+    pub async fn next_method(&mut self) -> Option<Method> {
+        self.methods.recv().await
+    }
+
+    // Skip methods until M is called on the proxy with id and return it.
+    // Panics if it is not called in time, so a test can not hang on a missing method
+    pub async fn wait_for_method<M>(&mut self, id: i32) -> M
+    where
+        M: for<'a> PodDeserialize<'a> + MessageOpCode,
+    {
+        let method = tokio::time::timeout(METHOD_TIMEOUT, async {
+            loop {
+                match self.methods.recv().await {
+                    Some(method) if method.is::<M>(id) => return method,
+                    Some(_) => continue,
+                    None => panic!("The client closed the connection"),
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| {
+            panic!(
+                "No method with opcode {} was called on id {} within {:?}",
+                M::OP_CODE,
+                id,
+                METHOD_TIMEOUT
+            )
+        });
+        method
+            .decode()
+            .unwrap_or_else(|| panic!("Method with opcode {} did not decode", M::OP_CODE))
+    }
+}
+
+// A global of type_ with props, readable, writable, executable and with metadata permissions
+pub fn global(id: i32, type_: &str, props: &[(&str, &str)]) -> Global {
+    let props = props
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    Global::new(id, 0x1c8, type_, 3, props)
+}
+
+// The next event of the registry, panics if it is not a global
+pub async fn next_global(registry: &mut RegistryProxy) -> Global {
+    match registry.recv().await {
+        Some(RegistryEvent::Global(global)) => global,
+        event => panic!("Expected a global, got {:?}", event),
+    }
+}
+
+async fn run_server(
+    mut reader: socket::SocketReader,
+    state: Arc<Mutex<ServerState>>,
+    methods: mpsc::UnboundedSender<Method>,
+) {
+    while let Ok(Some(message)) = reader.read_message().await {
+        let method = Method {
+            id: message.header.id,
+            opcode: message.header.opcode(),
+            seq: message.header.seq,
+            payload: message.payload.to_vec(),
+            fds: message.fds,
+        };
+        if handle_method(&state, &method).await.is_err() {
+            break;
+        }
+        // The test may not care about the methods at all
+        let _ = methods.send(method);
+    }
+}
+
+// Answer the methods the daemon itself would answer
+async fn handle_method(state: &Mutex<ServerState>, method: &Method) -> io::Result<()> {
+    let mut state = state.lock().await;
+    let state = &mut *state;
+    if let Some(generation) = footer::generation(&method.footers(), FOOTER_CLIENT_GENERATION) {
+        state.client_generation = generation;
+    }
+    if method.id == CORE_ID {
+        if let Some(sync) = method.decode::<core_proxy::Sync>() {
+            state
+                .writer
+                .call_method(
+                    CORE_ID,
+                    Done::OP_CODE,
+                    Done {
+                        id: sync.id,
+                        seq: sync.seq,
+                    },
+                )
+                .await?;
+        } else if let Some(get_registry) = method.decode::<core_proxy::GetRegistry>() {
+            state.registries.push(get_registry.new_id);
+            for global in state.globals.clone() {
+                state
+                    .writer
+                    .call_method(get_registry.new_id, Global::OP_CODE, global)
+                    .await?;
+            }
+        } else if let Some(create) = method.decode::<core_proxy::CreateObject>() {
+            let known = state.globals.iter().any(|global| {
+                global.type_ == "PipeWire:Interface:Factory"
+                    && global.props.get("factory.name") == Some(&create.factory_name)
+            });
+            if !known {
+                let message = format!("unknown factory {}", create.factory_name);
+                return fail_new_id(state, create.new_id, method.seq, message).await;
+            }
+        } else if let Some(destroy) = method.decode::<core_proxy::Destroy>() {
+            state.registries.retain(|id| *id != destroy.id);
+            state.bound.remove(&destroy.id);
+            if state.acknowledge_destroy {
+                state
+                    .writer
+                    .call_method(CORE_ID, RemoveId::OP_CODE, RemoveId { id: destroy.id })
+                    .await?;
+            }
+        }
+    } else if method.id == ClientProxy::CLIENT_ID {
+        if let Some(update) = method.decode::<client::UpdatePermissions>() {
+            for (id, permissions) in update.permissions {
+                match state.permissions.iter_mut().find(|(known, _)| *known == id) {
+                    Some(entry) => entry.1 = permissions,
+                    None => state.permissions.push((id, permissions)),
+                }
+            }
+        } else if let Some(get) = method.decode::<client::GetPermissions>() {
+            let start = (get.index.max(0) as usize).min(state.permissions.len());
+            let end = start.saturating_add(get.num.max(0) as usize);
+            let permissions = state.permissions[start..end.min(state.permissions.len())].to_vec();
+            state
+                .writer
+                .call_method(
+                    ClientProxy::CLIENT_ID,
+                    client::Permissions::OP_CODE,
+                    client::Permissions {
+                        index: get.index,
+                        permissions: PermissionList(permissions),
+                    },
+                )
+                .await?;
+        }
+    } else if state.registries.contains(&method.id) {
+        if let Some(bind) = method.decode::<registry::Bind>() {
+            let known = state
+                .globals
+                .iter()
+                .any(|global| global.id == bind.id && global.generation <= state.client_generation);
+            if !known {
+                let message = format!("unknown global {}", bind.id);
+                return fail_new_id(state, bind.new_id, method.seq, message).await;
+            }
+            state.bound.insert(bind.new_id, bind.id);
+            state
+                .writer
+                .call_method(
+                    CORE_ID,
+                    BoundId::OP_CODE,
+                    BoundId {
+                        id: bind.new_id,
+                        global_id: bind.id,
+                    },
+                )
+                .await?;
+        }
+    } else if let Some(global_id) = state.bound.get(&method.id).copied() {
+        // Nodes, ports and devices enumerate their params with the same method and event
+        if let Some(enum_params) = method.decode::<param::EnumParams>() {
+            let params: Vec<Value> = state
+                .params
+                .iter()
+                .filter(|(global, id, _)| *global == global_id && *id == enum_params.id)
+                .map(|(_, _, param)| param.clone())
+                .collect();
+            let num = match enum_params.num {
+                0 => params.len(),
+                num => num.max(0) as usize,
+            };
+            let start = enum_params.index.max(0) as usize;
+            for (index, param) in params.into_iter().enumerate().skip(start).take(num) {
+                let param = param::Param {
+                    seq: enum_params.seq,
+                    id: enum_params.id,
+                    index: index as i32,
+                    next: index as i32 + 1,
+                    param,
+                };
+                state
+                    .writer
+                    .call_method(method.id, param::Param::OP_CODE, param)
+                    .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Report that the object with new_id could not be made, the id is removed again like the daemon does
+async fn fail_new_id(
+    state: &mut ServerState,
+    new_id: i32,
+    seq: i32,
+    message: String,
+) -> io::Result<()> {
+    let error = ErrorEvent {
+        id: new_id,
+        seq,
+        res: -2, // ENOENT
+        message,
+    };
+    state
+        .writer
+        .call_method(CORE_ID, ErrorEvent::OP_CODE, error)
+        .await?;
+    state
+        .writer
+        .call_method(CORE_ID, RemoveId::OP_CODE, RemoveId { id: new_id })
+        .await
+}
@@ -1,21 +1,74 @@
 use std::collections::HashMap;
 
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+
+use crate::proxy;
+
+proxy::define_proxy! {
+    pub struct ModuleProxy: ModuleEvent {}
+    info(Info, ModuleEvent::Info)
+    bindable("PipeWire:Interface:Module", 3, module, module_proxies)
+}
+
 // === Methods ===
 
 // Module has no methods
 
 // === Events ===
-pub enum ModuleEvent{
-    Info(Info)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum ModuleEvent {
+        Info(Info),
+    }
 }
-#[derive(PodSerialize, PodDeserialize, Debug)]
+#[derive(PodSerialize, PodDeserialize, Debug, Clone, PartialEq)]
+#[opcode(0)]
 pub struct Info {
     pub id: i32,
     pub name: String,
     pub file_name: String,
-    pub args: String,
-    pub change_mask: i64,
+    pub args: Option<String>,
+    pub change_mask: i64, // Which fields changed since the previous info, see CHANGE_MASK_*
     pub props: HashMap<String, String>,
 }
+
+impl Info {
+    pub const CHANGE_MASK_PROPS: i64 = 1 << 0;
+    pub const CHANGE_MASK_ALL: i64 = Self::CHANGE_MASK_PROPS;
+}
+
+// The name, file name and args do not change once the module is loaded
+impl proxy::MergeInfo for Info {
+    const CHANGE_MASK_PROPS: i64 = Self::CHANGE_MASK_PROPS;
+
+    fn change_mask(&mut self) -> &mut i64 {
+        &mut self.change_mask
+    }
+
+    fn props(&mut self) -> &mut HashMap<String, String> {
+        &mut self.props
+    }
+}
+
+impl opcode::DeserializeFromOpCode for ModuleEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Info::OP_CODE => {
+                let (remain, value): (&[u8], Info) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ModuleEvent::Info(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
+}
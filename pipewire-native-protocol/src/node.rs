@@ -1,23 +1,63 @@
 use std::collections::HashMap;
 
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
-// === Methods ===
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct SubscribeParams {
-    pub ids: Vec<spa::value::Id>,
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+    value::{Id, Value},
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+use tokio::io;
+
+use crate::{
+    param::ParamInfos,
+    proxy,
+};
+
+proxy::define_proxy! {
+    pub struct NodeProxy: NodeEvent {}
+    params(NodeEvent::Param)
+    bindable("PipeWire:Interface:Node", 3, node, node_proxies)
 }
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct EnumParams {
-    pub seq: i32,
-    pub id: spa::value::Id,
-    pub index: i32,
-    pub num: i32,
-    pub filter: spa::value::Value,
+impl NodeProxy {
+    pub async fn set_param(&self, id: Id, flags: i32, param: Value) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(
+                &self.proxies,
+                self.id,
+                SetParam::OP_CODE,
+                SetParam { id, flags, param },
+            )
+            .await?;
+        pending_sync.await
+    }
+
+    pub async fn send_command(&self, command: Value) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(
+                &self.proxies,
+                self.id,
+                SendCommand::OP_CODE,
+                SendCommand { command },
+            )
+            .await?;
+        pending_sync.await
+    }
 }
 
+// === Methods ===
+// The methods for params and the Param event are shared by nodes, ports and devices
+pub use crate::param::{EnumParams, Param, SubscribeParams};
+
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(3)]
 pub struct SetParam {
     pub id: spa::value::Id,
     pub flags: i32,
@@ -25,17 +65,22 @@ pub struct SetParam {
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(4)]
 pub struct SendCommand {
     pub command: spa::value::Value,
 }
 
 // === Events ===
-pub enum NodeEvent {
-    Info(Info),
-    Param(Param)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum NodeEvent {
+        Info(Info),
+        Param(Param),
+    }
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(0)]
 pub struct Info {
     pub id: i32,
     pub max_input_ports: i32,
@@ -43,17 +88,30 @@ pub struct Info {
     pub change_mask: i64,
     pub n_input_ports: i32,
     pub n_output_ports: i32,
-    pub state: spa::value::Id, // Can this really be true?
-    pub error: String,
+    pub state: spa::value::Id, // pw_node_state, error is -1 and running is 3
+    pub error: Option<String>,
     pub props: HashMap<String, String>,
-    pub param_info: HashMap<i32, i32>,
+    pub param_info: ParamInfos,
 }
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct Param {
-    pub seq: i32,
-    pub id: spa::value::Id,
-    pub index: i32,
-    pub next: i32,
-    pub param: spa::value::Value,
+impl opcode::DeserializeFromOpCode for NodeEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Info::OP_CODE => {
+                let (remain, value): (&[u8], Info) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, NodeEvent::Info(value)))
+            }
+            Param::OP_CODE => {
+                let (remain, value): (&[u8], Param) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, NodeEvent::Param(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
 }
@@ -0,0 +1,154 @@
+// Param ids and object types from SPA, the information about the params of an object as sent in
+// the info events of nodes, ports and devices, and the methods and event they share for params
+use std::ops::Deref;
+
+use spa::{
+    deserialize::{
+        DeserializeError, DeserializeSuccess, PodDeserialize, PodDeserializer,
+        StructPodDeserializer, Visitor,
+    },
+    serialize::{GenError, PodSerialize, PodSerializer, SerializeSuccess},
+    value::Id,
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+
+// Param ids, see spa_param_type
+pub const PARAM_PROP_INFO: Id = Id(1);
+pub const PARAM_PROPS: Id = Id(2);
+pub const PARAM_ENUM_FORMAT: Id = Id(3);
+pub const PARAM_FORMAT: Id = Id(4);
+pub const PARAM_BUFFERS: Id = Id(5);
+pub const PARAM_META: Id = Id(6);
+pub const PARAM_IO: Id = Id(7);
+pub const PARAM_ENUM_PROFILE: Id = Id(8);
+pub const PARAM_PROFILE: Id = Id(9);
+pub const PARAM_ENUM_PORT_CONFIG: Id = Id(10);
+pub const PARAM_PORT_CONFIG: Id = Id(11);
+pub const PARAM_ENUM_ROUTE: Id = Id(12);
+pub const PARAM_ROUTE: Id = Id(13);
+pub const PARAM_CONTROL: Id = Id(14);
+pub const PARAM_LATENCY: Id = Id(15);
+pub const PARAM_PROCESS_LATENCY: Id = Id(16);
+pub const PARAM_TAG: Id = Id(17);
+
+// Object types of params, used as spa::value::Object::type_
+pub const OBJECT_PROP_INFO: u32 = 0x40001;
+pub const OBJECT_PROPS: u32 = 0x40002;
+pub const OBJECT_FORMAT: u32 = 0x40003;
+pub const OBJECT_PARAM_BUFFERS: u32 = 0x40004;
+pub const OBJECT_PARAM_META: u32 = 0x40005;
+pub const OBJECT_PARAM_IO: u32 = 0x40006;
+pub const OBJECT_PARAM_PROFILE: u32 = 0x40007;
+pub const OBJECT_PARAM_PORT_CONFIG: u32 = 0x40008;
+pub const OBJECT_PARAM_ROUTE: u32 = 0x40009;
+pub const OBJECT_PARAM_LATENCY: u32 = 0x4000b;
+pub const OBJECT_PARAM_PROCESS_LATENCY: u32 = 0x4000c;
+pub const OBJECT_PARAM_TAG: u32 = 0x4000d;
+
+// Values of the available key in profiles and routes, see spa_param_availability
+pub const AVAILABILITY_UNKNOWN: Id = Id(0);
+pub const AVAILABILITY_NO: Id = Id(1);
+pub const AVAILABILITY_YES: Id = Id(2);
+
+// Flags of a param, see spa_param_info
+pub const PARAM_INFO_SERIAL: i32 = 1 << 0; // Bit flipped when the param changed
+pub const PARAM_INFO_READ: i32 = 1 << 1; // The param is readable
+pub const PARAM_INFO_WRITE: i32 = 1 << 2; // The param is writable
+pub const PARAM_INFO_READWRITE: i32 = PARAM_INFO_READ | PARAM_INFO_WRITE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamInfo {
+    pub id: Id,
+    pub flags: i32,
+}
+
+// The params of an object, sent as Struct(Int n_params, (Id id, Int flags)*)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParamInfos(pub Vec<ParamInfo>);
+
+impl Deref for ParamInfos {
+    type Target = [ParamInfo];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl PodSerialize for ParamInfos {
+    fn serialize<O: std::io::Write + std::io::Seek>(
+        &self,
+        serializer: PodSerializer<O>,
+    ) -> Result<SerializeSuccess<O>, GenError> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field(&(self.0.len() as i32))?;
+        for info in &self.0 {
+            serializer.serialize_field(&info.id)?;
+            serializer.serialize_field(&info.flags)?;
+        }
+        serializer.end()
+    }
+}
+
+impl<'de> PodDeserialize<'de> for ParamInfos {
+    fn deserialize(
+        deserializer: PodDeserializer<'de>,
+    ) -> Result<(Self, DeserializeSuccess<'de>), DeserializeError<&'de [u8]>>
+    where
+        Self: Sized,
+    {
+        struct ParamInfosVisitor;
+        impl<'de> Visitor<'de> for ParamInfosVisitor {
+            type Value = ParamInfos;
+            type ArrayElem = std::convert::Infallible;
+
+            fn visit_struct(
+                &self,
+                struct_deserializer: &mut StructPodDeserializer<'de>,
+            ) -> Result<Self::Value, DeserializeError<&'de [u8]>> {
+                let n_params: i32 = struct_deserializer
+                    .deserialize_field()?
+                    .ok_or(DeserializeError::PropertyMissing)?;
+                let mut params = Vec::with_capacity(n_params.max(0) as usize);
+                for _ in 0..n_params {
+                    let id = struct_deserializer
+                        .deserialize_field()?
+                        .ok_or(DeserializeError::PropertyMissing)?;
+                    let flags = struct_deserializer
+                        .deserialize_field()?
+                        .ok_or(DeserializeError::PropertyMissing)?;
+                    params.push(ParamInfo { id, flags });
+                }
+                Ok(ParamInfos(params))
+            }
+        }
+        deserializer.deserialize_struct(ParamInfosVisitor)
+    }
+}
+
+// === Methods ===
+#[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(1)]
+pub struct SubscribeParams {
+    pub ids: Vec<spa::value::Id>,
+}
+
+#[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(2)]
+pub struct EnumParams {
+    pub seq: i32,
+    pub id: spa::value::Id,
+    pub index: i32,
+    pub num: i32,
+    pub filter: spa::value::Value,
+}
+
+// === Events ===
+#[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(1)]
+pub struct Param {
+    pub seq: i32,
+    pub id: spa::value::Id,
+    pub index: i32,
+    pub next: i32,
+    pub param: spa::value::Value,
+}
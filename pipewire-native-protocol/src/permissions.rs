@@ -0,0 +1,123 @@
+// Permissions of a client on the globals, see pw_permission.
+// The server keeps a permission per global id, with ID_ANY as the default for the other globals
+use std::ops::Deref;
+
+use bitflags::bitflags;
+use spa::{
+    deserialize::{
+        DeserializeError, DeserializeSuccess, PodDeserialize, PodDeserializer,
+        StructPodDeserializer, Visitor,
+    },
+    serialize::{GenError, PodSerialize, PodSerializer, SerializeSuccess},
+};
+
+// The id of the default permission, used for globals without a permission of their own
+pub const ID_ANY: i32 = -1;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Permissions: u32 {
+        const R = 0o400; // The global is visible and can be bound
+        const W = 0o200; // Methods that change the object can be called
+        const X = 0o100; // Methods that do not change the object can be called
+        const M = 0o010; // Metadata can be set on the object
+        const L = 0o020; // A link can be made between the nodes, even without W on them
+    }
+}
+
+impl Permissions {
+    pub const RWX: Permissions = Permissions::R.union(Permissions::W).union(Permissions::X);
+    pub const ALL: Permissions = Permissions::RWX.union(Permissions::M).union(Permissions::L);
+}
+
+// Permissions are sent as Int, bits this version does not know about are kept
+impl PodSerialize for Permissions {
+    fn serialize<O: std::io::Write + std::io::Seek>(
+        &self,
+        serializer: PodSerializer<O>,
+    ) -> Result<SerializeSuccess<O>, GenError> {
+        (self.bits() as i32).serialize(serializer)
+    }
+}
+
+impl<'de> PodDeserialize<'de> for Permissions {
+    fn deserialize(
+        deserializer: PodDeserializer<'de>,
+    ) -> Result<(Self, DeserializeSuccess<'de>), DeserializeError<&'de [u8]>>
+    where
+        Self: Sized,
+    {
+        let (bits, success) = i32::deserialize(deserializer)?;
+        Ok((Permissions::from_bits_retain(bits as u32), success))
+    }
+}
+
+// Permissions by global id, sent as Struct(Int n_permissions, (Int id, Int permissions)*)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PermissionList(pub Vec<(i32, Permissions)>);
+
+impl Deref for PermissionList {
+    type Target = [(i32, Permissions)];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl PodSerialize for PermissionList {
+    fn serialize<O: std::io::Write + std::io::Seek>(
+        &self,
+        serializer: PodSerializer<O>,
+    ) -> Result<SerializeSuccess<O>, GenError> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field(&(self.0.len() as i32))?;
+        for (id, permissions) in &self.0 {
+            serializer.serialize_field(id)?;
+            serializer.serialize_field(permissions)?;
+        }
+        serializer.end()
+    }
+}
+
+impl<'de> PodDeserialize<'de> for PermissionList {
+    fn deserialize(
+        deserializer: PodDeserializer<'de>,
+    ) -> Result<(Self, DeserializeSuccess<'de>), DeserializeError<&'de [u8]>>
+    where
+        Self: Sized,
+    {
+        struct PermissionListVisitor;
+        impl<'de> Visitor<'de> for PermissionListVisitor {
+            type Value = PermissionList;
+            type ArrayElem = std::convert::Infallible;
+
+            fn visit_struct(
+                &self,
+                struct_deserializer: &mut StructPodDeserializer<'de>,
+            ) -> Result<Self::Value, DeserializeError<&'de [u8]>> {
+                deserialize_permissions(struct_deserializer).map(PermissionList)
+            }
+        }
+        deserializer.deserialize_struct(PermissionListVisitor)
+    }
+}
+
+// Read n_permissions followed by the pairs, the list is embedded like this in other structs too
+pub(crate) fn deserialize_permissions<'de>(
+    struct_deserializer: &mut StructPodDeserializer<'de>,
+) -> Result<Vec<(i32, Permissions)>, DeserializeError<&'de [u8]>> {
+    let n_permissions: i32 = struct_deserializer
+        .deserialize_field()?
+        .ok_or(DeserializeError::PropertyMissing)?;
+    let mut permissions = Vec::with_capacity(n_permissions.max(0) as usize);
+    for _ in 0..n_permissions {
+        let id = struct_deserializer
+            .deserialize_field()?
+            .ok_or(DeserializeError::PropertyMissing)?;
+        let bits = struct_deserializer
+            .deserialize_field()?
+            .ok_or(DeserializeError::PropertyMissing)?;
+        permissions.push((id, bits));
+    }
+    Ok(permissions)
+}
@@ -1,42 +1,64 @@
 use std::collections::HashMap;
 
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
-// === Methods ===
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct SubscribeParams {
-    pub ids: Vec<spa::value::Id>,
-}
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct EnumParams {
-    pub seq: i32,
-    pub id: spa::value::Id,
-    pub index: i32,
-    pub num: i32,
-    pub filter: spa::value::Value,
+use crate::{
+    param::ParamInfos,
+    proxy,
+};
+
+proxy::define_proxy! {
+    pub struct PortProxy: PortEvent {}
+    params(PortEvent::Param)
+    bindable("PipeWire:Interface:Port", 3, port, port_proxies)
 }
 
+// === Methods ===
+// The methods for params and the Param event are shared by nodes, ports and devices
+pub use crate::param::{EnumParams, Param, SubscribeParams};
+
 // === Events ===
-pub enum PortEvent {
-    Info(Info),
-    Param(Param)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum PortEvent {
+        Info(Info),
+        Param(Param),
+    }
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(0)]
 pub struct Info {
     pub id: i32,
-    pub direction: i32,
+    pub direction: i32, // 0 is input and 1 is output
     pub change_mask: i64,
     pub props: HashMap<String, String>,
-    pub param_info: HashMap<i32, i32>,
+    pub param_info: ParamInfos,
 }
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
-pub struct Param {
-    pub seq: i32,
-    pub id: spa::value::Id,
-    pub index: i32,
-    pub next: i32,
-    pub param: spa::value::Value,
+impl opcode::DeserializeFromOpCode for PortEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Info::OP_CODE => {
+                let (remain, value): (&[u8], Info) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, PortEvent::Info(value)))
+            }
+            Param::OP_CODE => {
+                let (remain, value): (&[u8], Param) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, PortEvent::Param(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
 }
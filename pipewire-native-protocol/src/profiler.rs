@@ -1,15 +1,259 @@
-use spa::serialize::PodSerializer;
-use spa_derive::{PodDeserialize, PodSerialize};
+use spa::{
+    deserialize::{DeserializeError, PodDeserializer},
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+    value::{Fraction, Object, Value},
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+
+use crate::proxy;
+
+proxy::define_proxy! {
+    pub struct ProfilerProxy: ProfilerEvent {}
+    bindable("PipeWire:Interface:Profiler", 3, profiler, profiler_proxies)
+}
+
 // === Methods ===
 
 // No methods for profiler
 
 // === Events ===
-pub enum ProfilerEvent {
-    Profile(Profile)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum ProfilerEvent {
+        Profile(Profile),
+    }
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(0)]
 pub struct Profile {
-    pub object: spa::value::Value,
+    pub object: Value, // A struct of profiler objects, see reports
+}
+
+impl opcode::DeserializeFromOpCode for ProfilerEvent {
+    fn deserialize_from_opcode(
+        opcode: u32,
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        match opcode {
+            Profile::OP_CODE => {
+                let (remain, value): (&[u8], Profile) = PodDeserializer::deserialize_from(buffer)?;
+                Ok((remain, ProfilerEvent::Profile(value)))
+            }
+            _ => Err(DeserializeError::InvalidType),
+        }
+    }
+}
+
+// The object type of the profiler objects, see spa_type
+pub const OBJECT_PROFILER: u32 = 0x4000a;
+
+impl Profile {
+    // Decode the profiler objects in the event, every object is one cycle of a driver.
+    // Objects that are not profiler objects are skipped
+    pub fn reports(&self) -> Vec<Report> {
+        let Value::Struct(objects) = &self.object else {
+            return Vec::new();
+        };
+        objects
+            .iter()
+            .filter_map(|object| match object {
+                Value::Object(object) => Report::from_object(object),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+// One cycle of a driver and the nodes that followed it, what pw-top shows a line for
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub info: Option<Info>,
+    pub clock: Option<Clock>,
+    pub driver: Option<Block>,
+    pub followers: Vec<Block>,
+}
+
+impl Report {
+    // Keys of the properties in a profiler object, see spa_profiler
+    pub const KEY_INFO: u32 = 0x10001;
+    pub const KEY_CLOCK: u32 = 0x10002;
+    pub const KEY_DRIVER_BLOCK: u32 = 0x10003;
+    pub const KEY_FOLLOWER_BLOCK: u32 = 0x20001;
+
+    // Parse the object, returns None if it is not a profiler object.
+    // Properties with unknown keys or that fail to parse are skipped
+    pub fn from_object(object: &Object) -> Option<Report> {
+        if object.type_ != OBJECT_PROFILER {
+            return None;
+        }
+        let mut report = Report {
+            info: None,
+            clock: None,
+            driver: None,
+            followers: Vec::new(),
+        };
+        for property in &object.properties {
+            let Value::Struct(fields) = &property.value else {
+                continue;
+            };
+            let fields = Fields(fields.iter());
+            match property.key {
+                Self::KEY_INFO => report.info = Info::parse(fields),
+                Self::KEY_CLOCK => report.clock = Clock::parse(fields),
+                Self::KEY_DRIVER_BLOCK => report.driver = Block::parse(fields),
+                Self::KEY_FOLLOWER_BLOCK => report.followers.extend(Block::parse(fields)),
+                _ => (),
+            }
+        }
+        Some(report)
+    }
+}
+
+// Counters of the process, sent as Struct(Long, Float, Float, Float, Int)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Info {
+    pub counter: i64,       // Incremented for every cycle
+    pub cpu_load: [f32; 3], // Averaged over a short, medium and long time
+    pub xrun_count: i32,
+}
+
+impl Info {
+    fn parse(mut fields: Fields) -> Option<Info> {
+        Some(Info {
+            counter: fields.long()?,
+            cpu_load: [fields.float()?, fields.float()?, fields.float()?],
+            xrun_count: fields.int()?,
+        })
+    }
+}
+
+// The clock of the driver in the cycle, times are in nanoseconds.
+// The fields at the end was added in later versions of the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clock {
+    pub flags: i32,
+    pub id: i32,
+    pub name: String,
+    pub nsec: i64, // Time of the cycle start
+    pub rate: Fraction,
+    pub position: i64, // In samples at rate
+    pub duration: i64, // The quantum, in samples at rate
+    pub delay: i64,
+    pub rate_diff: f64,
+    pub next_nsec: i64, // Expected time of the next cycle
+    pub transport_state: Option<i32>,
+    pub cycle: Option<i32>,
+    pub xrun_duration: Option<i64>,
+}
+
+impl Clock {
+    fn parse(mut fields: Fields) -> Option<Clock> {
+        Some(Clock {
+            flags: fields.int()?,
+            id: fields.int()?,
+            name: fields.string()?,
+            nsec: fields.long()?,
+            rate: fields.fraction()?,
+            position: fields.long()?,
+            duration: fields.long()?,
+            delay: fields.long()?,
+            rate_diff: fields.double()?,
+            next_nsec: fields.long()?,
+            transport_state: fields.int(),
+            cycle: fields.int(),
+            xrun_duration: fields.long(),
+        })
+    }
+}
+
+// The timing of a driver or follower node in the cycle, times are in nanoseconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: i32, // Of the node
+    pub name: String,
+    pub prev_signal: i64, // When the node was signaled in the previous cycle
+    pub signal: i64,      // When the node was signaled to start processing
+    pub awake: i64,       // When the node started processing
+    pub finish: i64,      // When the node was done processing
+    pub status: i32,      // The activation status of the node
+    pub latency: Fraction,
+    pub xrun_count: Option<i32>, // Not sent by older versions of the server
+}
+
+impl Block {
+    fn parse(mut fields: Fields) -> Option<Block> {
+        Some(Block {
+            id: fields.int()?,
+            name: fields.string()?,
+            prev_signal: fields.long()?,
+            signal: fields.long()?,
+            awake: fields.long()?,
+            finish: fields.long()?,
+            status: fields.int()?,
+            latency: fields.fraction()?,
+            xrun_count: fields.int(),
+        })
+    }
+
+    // Time from being signaled until processing started, the WAIT column of pw-top
+    pub fn waiting(&self) -> i64 {
+        self.awake - self.signal
+    }
+
+    // Time spent processing, the BUSY column of pw-top
+    pub fn busy(&self) -> i64 {
+        self.finish - self.awake
+    }
+}
+
+// The fields of a struct, read in order
+struct Fields<'a>(std::slice::Iter<'a, Value>);
+
+impl Fields<'_> {
+    fn int(&mut self) -> Option<i32> {
+        match self.0.next()? {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn long(&mut self) -> Option<i64> {
+        match self.0.next()? {
+            Value::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn float(&mut self) -> Option<f32> {
+        match self.0.next()? {
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn double(&mut self) -> Option<f64> {
+        match self.0.next()? {
+            Value::Double(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        match self.0.next()? {
+            Value::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    fn fraction(&mut self) -> Option<Fraction> {
+        match self.0.next()? {
+            Value::Fraction(value) => Some(*value),
+            _ => None,
+        }
+    }
 }
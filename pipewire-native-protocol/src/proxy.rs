@@ -1,22 +1,556 @@
-pub trait Proxy {
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::sync::{mpsc::Receiver, Mutex};
+
+use crate::{builder::ChannelCapacities, core_proxy, error::ServerError, PipewireWriter};
+
+// The parts of the proxy traits that refer to the connection internals. The module is private, so
+// the traits can not be implemented or called outside of the crate, and the crate private types in
+// them are never reachable
+#[allow(private_interfaces)]
+pub(crate) mod internal {
+    use std::sync::Arc;
+
+    use tokio::sync::{
+        mpsc::{Receiver, Sender},
+        Mutex,
+    };
+
+    use crate::{
+        core_proxy::Done,
+        error::{DisconnectReason, ServerError},
+        PipewireWriter, Proxies,
+    };
+
+    pub trait ProxyInternal {
+        fn get_connection(&self) -> Arc<Mutex<PipewireWriter>>;
+        fn get_proxies(&self) -> Arc<Mutex<Proxies>>;
+    }
+
+    // The events the connection sends to every kind of proxy, implemented by define_events
+    pub trait ProxyEvent {
+        fn done(done: Done) -> Self;
+        fn error(error: ServerError) -> Self;
+        fn connection_lost(reason: DisconnectReason) -> Self;
+        fn reconnected() -> Self;
+        fn disconnected(reason: DisconnectReason) -> Self;
+    }
+
+    pub trait BindableProxyInternal: Sized {
+        type Event;
+
+        // Register the channel that events for the proxy with id are routed to
+        fn register(proxies: &mut Proxies, id: i32, sender: Sender<Self::Event>);
+
+        fn new(
+            id: i32,
+            connection: Arc<Mutex<PipewireWriter>>,
+            event_receiver: Receiver<Self::Event>,
+            proxies: Arc<Mutex<Proxies>>,
+        ) -> Self;
+    }
+}
+
+pub trait Proxy: internal::ProxyInternal {
     type Event;
-    
+
     fn id(&self) -> i32;
     fn get_channel(&mut self) -> &mut tokio::sync::mpsc::Receiver<Self::Event>;
-    fn get_connection(&self) -> std::sync::Arc<tokio::sync::Mutex<crate::PipewireWriter>>;
 
+    // The id of the global the proxy is bound to, known once the server has sent a BoundId event
+    fn global_id(&self) -> impl Future<Output = Option<i32>> + Send {
+        let proxies = self.get_proxies();
+        let id = self.id();
+        async move { proxies.lock().await.bound_ids.get(&id).copied() }
+    }
+
+    // Destroy the proxy and the server side object it refers to.
+    // The id is reused for new proxies when the server has acknowledged the removal
+    fn destroy(mut self) -> impl Future<Output = io::Result<()>> + Send
+    where
+        Self: Sized,
+    {
+        let con = self.get_connection();
+        let id = self.id();
+        self.get_channel().close(); // Marks the proxy as destroyed, so dropping it does nothing
+        async move {
+            con.lock()
+                .await
+                .call_method(
+                    core_proxy::CORE_ID,
+                    <core_proxy::Destroy as spa::opcode::MessageOpCode>::OP_CODE,
+                    core_proxy::Destroy { id },
+                )
+                .await
+        }
+    }
 
     // Send a sync message through the connection with the Id of the current proxy
     // When we receive a done message we route it to the proxy with the id
     // This is not absolute standard pipewire according to spec.
     // But it seems like accepted usage according to the tutorials
-    async fn sync(&mut self) -> Result<(), std::io::Error> {
+    // The returned PendingSync can be awaited for the done message
+    fn sync(&mut self) -> impl Future<Output = Result<PendingSync, std::io::Error>> + Send {
         let con = self.get_connection();
-        let mut connection = con.lock().await;
-        let seq = connection.seq + 1; 
-        connection.call_method(crate::core_proxy::CORE_ID, <crate::core_proxy::Sync as spa::opcode::MessageOpCode>::OP_CODE, crate::core_proxy::Sync {
-            id: self.id(),
-            seq: seq,
-        }).await
-    }
-}
\ No newline at end of file
+        let proxies = self.get_proxies();
+        let id = self.id();
+        async move { con.lock().await.sync(&proxies, id).await }
+    }
+
+    // Wait until the server has processed everything sent before it.
+    // Events received meanwhile are delivered to the proxy channels, they do not need to be read first.
+    // The done event that answers the sync only resolves it and is not delivered as an event
+    fn roundtrip(&mut self) -> impl Future<Output = Result<(), std::io::Error>> + Send {
+        let sync = self.sync();
+        async move { sync.await?.await }
+    }
+}
+
+// A sync that was sent, resolves when the server has answered it with a done event.
+// Fails with the ServerError if a method called before it on the same proxy failed
+#[derive(Debug)]
+#[must_use = "the sync is only waited for when the PendingSync is awaited"]
+pub struct PendingSync {
+    seq: i32,
+    receiver: tokio::sync::oneshot::Receiver<Result<(), ServerError>>,
+}
+
+impl PendingSync {
+    pub(crate) fn new(
+        seq: i32,
+        receiver: tokio::sync::oneshot::Receiver<Result<(), ServerError>>,
+    ) -> Self {
+        Self { seq, receiver }
+    }
+
+    // The seq of the done event that resolves the sync
+    pub fn seq(&self) -> i32 {
+        self.seq
+    }
+}
+
+impl Future for PendingSync {
+    type Output = io::Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|result| match result {
+                Ok(result) => result.map_err(io::Error::from),
+                Err(_) => Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "Connection closed before the sync was done",
+                )),
+            })
+    }
+}
+
+// A proxy for an interface that can be bound from a registry global
+pub trait BindableProxy:
+    Proxy + internal::BindableProxyInternal<Event = <Self as Proxy>::Event>
+{
+    // The interface type string, as found in registry::Global::type_
+    const TYPE: &'static str;
+    // The highest version of the interface the proxy implements
+    const VERSION: i32;
+
+    // The size of the event channel for new proxies of this type
+    fn channel_capacity(capacities: &ChannelCapacities) -> usize;
+}
+
+// An info that the server sends in full once and then as updates, where the props are only sent
+// when CHANGE_MASK_PROPS is set and the other fields are sent every time
+pub trait MergeInfo: Clone {
+    const CHANGE_MASK_PROPS: i64;
+
+    fn change_mask(&mut self) -> &mut i64;
+    fn props(&mut self) -> &mut HashMap<String, String>;
+
+    // Apply an update. The change mask is the one of the update, so it tells what the latest
+    // update changed
+    fn merge(&mut self, update: &Self) {
+        let mut props = std::mem::take(self.props());
+        *self = update.clone();
+        if *self.change_mask() & Self::CHANGE_MASK_PROPS == 0 {
+            std::mem::swap(self.props(), &mut props);
+        }
+    }
+}
+
+// Defines a proxy struct with the fields every proxy has, followed by the extra fields given, which
+// start out with their default value. The proxy implements Proxy and is destroyed when dropped. Its
+// channel is private, events are received with recv and try_recv so no event can skip the local
+// state. With on_event the given method is called with every event taken from the channel, to keep
+// local state up to date. With info the proxy keeps the given MergeInfo up to date
+// from the given event variant and gets info and wait_for_info. With params the proxy gets the
+// methods for the params of nodes, ports and devices, which arrive as the given event variant. With
+// the bindable part it also implements BindableProxy, registering its channel in the given map of
+// Proxies
+macro_rules! define_proxy {
+    (
+        pub struct $name:ident: $event:ty {
+            $($field:ident: $field_type:ty,)*
+        }
+        $(on_event($on_event:ident))?
+        $(info($info:ty, $info_event:path))?
+        $(params($param:path))?
+        $(bindable($type_:literal, $version:literal, $capacity:ident, $map:ident))?
+    ) => {
+        pub struct $name {
+            id: i32,
+            connection: std::sync::Arc<tokio::sync::Mutex<$crate::PipewireWriter>>,
+            event_receiver: tokio::sync::mpsc::Receiver<$event>,
+            // Events taken from the channel while a method waited for the server, recv delivers
+            // them before the channel
+            buffered: std::collections::VecDeque<$event>,
+            proxies: std::sync::Arc<tokio::sync::Mutex<$crate::Proxies>>,
+            $(info: Option<$info>,)?
+            $($field: $field_type,)*
+        }
+
+        impl $name {
+            pub(crate) fn from_parts(
+                id: i32,
+                connection: std::sync::Arc<tokio::sync::Mutex<$crate::PipewireWriter>>,
+                event_receiver: tokio::sync::mpsc::Receiver<$event>,
+                proxies: std::sync::Arc<tokio::sync::Mutex<$crate::Proxies>>,
+            ) -> Self {
+                $name {
+                    id,
+                    connection,
+                    event_receiver,
+                    buffered: std::collections::VecDeque::new(),
+                    proxies,
+                    $(info: Option::<$info>::None,)?
+                    $($field: Default::default(),)*
+                }
+            }
+
+            // Receive the next event. Receiving through the Receiver directly skips the events
+            // that were buffered while a method waited for the server
+            pub async fn recv(&mut self) -> Option<$event> {
+                if let Some(event) = self.buffered.pop_front() {
+                    return Some(event);
+                }
+                let event = self.event_receiver.recv().await?;
+                self.handle_event(&event);
+                Some(event)
+            }
+
+            pub fn try_recv(&mut self) -> Result<$event, tokio::sync::mpsc::error::TryRecvError> {
+                if let Some(event) = self.buffered.pop_front() {
+                    return Ok(event);
+                }
+                let event = self.event_receiver.try_recv()?;
+                self.handle_event(&event);
+                Ok(event)
+            }
+
+            // Wait for pending_sync while taking the events from the channel. take gets every
+            // event and gives back the ones it has no use for, they are buffered for recv
+            pub async fn receive_until(
+                &mut self,
+                mut pending_sync: $crate::proxy::PendingSync,
+                mut take: impl FnMut($event) -> Option<$event>,
+            ) -> std::io::Result<()> {
+                loop {
+                    tokio::select! {
+                        result = &mut pending_sync => {
+                            result?;
+                            break;
+                        }
+                        event = self.event_receiver.recv() => match event {
+                            Some(event) => {
+                                self.handle_event(&event);
+                                self.buffered.extend(take(event));
+                            }
+                            None => {
+                                return Err(std::io::Error::new(
+                                    std::io::ErrorKind::BrokenPipe,
+                                    "Connection closed while waiting for the server",
+                                ))
+                            }
+                        },
+                    }
+                }
+                // The events sent before the done are all in the channel by now
+                while let Ok(event) = self.event_receiver.try_recv() {
+                    self.handle_event(&event);
+                    self.buffered.extend(take(event));
+                }
+                Ok(())
+            }
+
+            // Keep the local state up to date with an event taken from the channel. Proxies
+            // without on_event and info have no local state and leave the event unused
+            fn handle_event(&mut self, _event: &$event) {
+                $(self.$on_event(_event);)?
+                $(
+                    if let $info_event(update) = _event {
+                        match &mut self.info {
+                            Some(info) => $crate::proxy::MergeInfo::merge(info, update),
+                            None => self.info = Some(update.clone()),
+                        }
+                    }
+                )?
+            }
+        }
+
+        $(
+            impl $name {
+                // Wait until the info that the server has sent so far is received, after binding
+                // this gives the complete info
+                pub async fn wait_for_info(&mut self) -> std::io::Result<&$info> {
+                    let pending_sync = self
+                        .connection
+                        .lock()
+                        .await
+                        .sync(&self.proxies, self.id)
+                        .await?;
+                    self.receive_until(pending_sync, Some).await?;
+                    self.info.as_ref().ok_or_else(|| {
+                        std::io::Error::new(std::io::ErrorKind::NotFound, "No info was received")
+                    })
+                }
+
+                // The info with all updates received so far merged, None before the first Info event
+                pub fn info(&self) -> Option<&$info> {
+                    self.info.as_ref()
+                }
+            }
+        )?
+
+        $(
+            impl $name {
+                pub async fn subscribe_params(&self, ids: Vec<spa::value::Id>) -> std::io::Result<()> {
+                    self.connection
+                        .lock()
+                        .await
+                        .call_method(
+                            self.id,
+                            <$crate::param::SubscribeParams as spa::opcode::MessageOpCode>::OP_CODE,
+                            $crate::param::SubscribeParams { ids },
+                        )
+                        .await
+                }
+
+                // Request params, the result is sent as Param events with the given seq.
+                // A num of 0 requests all params starting at index
+                pub async fn send_enum_params(
+                    &self,
+                    seq: i32,
+                    id: spa::value::Id,
+                    index: i32,
+                    num: i32,
+                    filter: spa::value::Value,
+                ) -> std::io::Result<()> {
+                    self.connection
+                        .lock()
+                        .await
+                        .call_method(
+                            self.id,
+                            <$crate::param::EnumParams as spa::opcode::MessageOpCode>::OP_CODE,
+                            $crate::param::EnumParams {
+                                seq,
+                                id,
+                                index,
+                                num,
+                                filter,
+                            },
+                        )
+                        .await
+                }
+
+                // Enumerate all params with the given id, filter is Value::None to get all of them.
+                // Collects Param events until the server is done, other events received meanwhile
+                // are kept for recv
+                pub async fn enum_params(
+                    &mut self,
+                    id: spa::value::Id,
+                    filter: spa::value::Value,
+                ) -> std::io::Result<Vec<$crate::param::Param>> {
+                    let (seq, pending_sync) = {
+                        let mut connection = self.connection.lock().await;
+                        let seq = connection.seq;
+                        connection
+                            .call_method(
+                                self.id,
+                                <$crate::param::EnumParams as spa::opcode::MessageOpCode>::OP_CODE,
+                                $crate::param::EnumParams {
+                                    seq,
+                                    id,
+                                    index: 0,
+                                    num: 0,
+                                    filter,
+                                },
+                            )
+                            .await?;
+                        (seq, connection.sync(&self.proxies, self.id).await?)
+                    };
+
+                    let mut params = Vec::new();
+                    self.receive_until(pending_sync, |event| match event {
+                        $param(param) if param.seq == seq => {
+                            params.push(param);
+                            None
+                        }
+                        event => Some(event),
+                    })
+                    .await?;
+                    Ok(params)
+                }
+            }
+        )?
+
+        impl $crate::proxy::Proxy for $name {
+            type Event = $event;
+
+            fn id(&self) -> i32 {
+                self.id
+            }
+
+            fn get_channel(&mut self) -> &mut tokio::sync::mpsc::Receiver<Self::Event> {
+                &mut self.event_receiver
+            }
+        }
+
+        #[allow(private_interfaces)] // The trait can not be named outside of the crate
+        impl $crate::proxy::internal::ProxyInternal for $name {
+            fn get_connection(&self) -> std::sync::Arc<tokio::sync::Mutex<$crate::PipewireWriter>> {
+                self.connection.clone()
+            }
+
+            fn get_proxies(&self) -> std::sync::Arc<tokio::sync::Mutex<$crate::Proxies>> {
+                self.proxies.clone()
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                $crate::proxy::destroy_on_drop(self.id, &self.connection, &self.event_receiver);
+            }
+        }
+
+        $(
+            impl $crate::proxy::BindableProxy for $name {
+                const TYPE: &'static str = $type_;
+                const VERSION: i32 = $version;
+
+                fn channel_capacity(capacities: &$crate::builder::ChannelCapacities) -> usize {
+                    capacities.$capacity
+                }
+            }
+
+            #[allow(private_interfaces)] // The trait can not be named outside of the crate
+            impl $crate::proxy::internal::BindableProxyInternal for $name {
+                type Event = $event;
+
+                fn register(
+                    proxies: &mut $crate::Proxies,
+                    id: i32,
+                    sender: tokio::sync::mpsc::Sender<Self::Event>,
+                ) {
+                    proxies.$map.insert(id, $crate::queue::EventSender::new(sender));
+                }
+
+                fn new(
+                    id: i32,
+                    connection: std::sync::Arc<tokio::sync::Mutex<$crate::PipewireWriter>>,
+                    event_receiver: tokio::sync::mpsc::Receiver<Self::Event>,
+                    proxies: std::sync::Arc<tokio::sync::Mutex<$crate::Proxies>>,
+                ) -> Self {
+                    $name::from_parts(id, connection, event_receiver, proxies)
+                }
+            }
+        )?
+    };
+}
+
+pub(crate) use define_proxy;
+
+// Defines the event enum of a proxy with the events of its interface, followed by the events the
+// connection sends to every proxy
+macro_rules! define_events {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident $(($($field:ty),*))?,)*
+        }
+    ) => {
+        $(#[$meta])*
+        pub enum $name {
+            $($(#[$variant_meta])* $variant $(($($field),*))?,)*
+            // The done of a sync on the proxy that nothing waited for
+            Done($crate::core_proxy::Done),
+            // An error the server reported for the proxy
+            Error($crate::error::ServerError),
+            // The connection was lost and is being reestablished, Reconnected follows if it succeeds
+            ConnectionLost($crate::error::DisconnectReason),
+            // The connection was restored and the proxy is usable again
+            Reconnected,
+            // The connection is gone, this is the last event the proxy receives
+            Disconnected($crate::error::DisconnectReason),
+        }
+
+        #[allow(private_interfaces)] // The trait can not be named outside of the crate
+        impl $crate::proxy::internal::ProxyEvent for $name {
+            fn done(done: $crate::core_proxy::Done) -> Self {
+                $name::Done(done)
+            }
+
+            fn error(error: $crate::error::ServerError) -> Self {
+                $name::Error(error)
+            }
+
+            fn connection_lost(reason: $crate::error::DisconnectReason) -> Self {
+                $name::ConnectionLost(reason)
+            }
+
+            fn reconnected() -> Self {
+                $name::Reconnected
+            }
+
+            fn disconnected(reason: $crate::error::DisconnectReason) -> Self {
+                $name::Disconnected(reason)
+            }
+        }
+    };
+}
+
+pub(crate) use define_events;
+
+// Destroy a proxy that is being dropped.
+// Nothing is sent if the proxy was already destroyed, or removed by the server which closes its channel.
+// Our own client lives as long as the connection, so it is never destroyed
+pub(crate) fn destroy_on_drop<E>(
+    id: i32,
+    connection: &Arc<Mutex<PipewireWriter>>,
+    event_receiver: &Receiver<E>,
+) {
+    if id == crate::client::ClientProxy::CLIENT_ID || event_receiver.is_closed() {
+        return;
+    }
+    // Without a runtime the connection can not be used anymore anyway
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    let connection = connection.clone();
+    runtime.spawn(async move {
+        let _ = connection
+            .lock()
+            .await
+            .call_method(
+                core_proxy::CORE_ID,
+                <core_proxy::Destroy as spa::opcode::MessageOpCode>::OP_CODE,
+                core_proxy::Destroy { id },
+            )
+            .await;
+    });
+}
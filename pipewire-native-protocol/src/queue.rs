@@ -0,0 +1,87 @@
+// The sending side of the event channel of a proxy. The reader task must never wait for a proxy
+// that is not being read: the done events of syncs and the pings behind its events would not be
+// read either. When the channel is full events queue up behind it in order, and a task moves them
+// into the channel as the proxy makes room.
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::mpsc::{error::TrySendError, Sender};
+
+pub(crate) struct EventSender<E> {
+    sender: Sender<E>,
+    backlog: Arc<Mutex<Backlog<E>>>,
+}
+
+struct Backlog<E> {
+    events: VecDeque<E>,
+    draining: bool, // A task is moving the events into the channel
+}
+
+impl<E> Clone for EventSender<E> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            backlog: self.backlog.clone(),
+        }
+    }
+}
+
+impl<E> std::fmt::Debug for EventSender<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventSender").finish_non_exhaustive()
+    }
+}
+
+impl<E: Send + 'static> EventSender<E> {
+    pub(crate) fn new(sender: Sender<E>) -> Self {
+        Self {
+            sender,
+            backlog: Arc::new(Mutex::new(Backlog {
+                events: VecDeque::new(),
+                draining: false,
+            })),
+        }
+    }
+
+    // Pass an event on after the ones sent before, without waiting for room in the channel.
+    // Returns false when the proxy is gone
+    pub(crate) fn send(&self, event: E) -> bool {
+        let mut backlog = self.backlog.lock().unwrap();
+        if backlog.events.is_empty() {
+            match self.sender.try_send(event) {
+                Ok(()) => return true,
+                Err(TrySendError::Closed(_)) => return false,
+                Err(TrySendError::Full(event)) => backlog.events.push_back(event),
+            }
+        } else if self.sender.is_closed() {
+            return false;
+        } else {
+            backlog.events.push_back(event);
+        }
+        if !backlog.draining {
+            backlog.draining = true;
+            tokio::spawn(drain(self.sender.clone(), self.backlog.clone()));
+        }
+        true
+    }
+}
+
+// Move the queued events into the channel until none are left. The events are taken while
+// holding the lock, so a send can not overtake them
+async fn drain<E>(sender: Sender<E>, backlog: Arc<Mutex<Backlog<E>>>) {
+    loop {
+        let permit = sender.reserve().await;
+        let mut backlog = backlog.lock().unwrap();
+        match (permit, backlog.events.pop_front()) {
+            (Ok(permit), Some(event)) => permit.send(event),
+            // Either nothing is left, or the proxy is gone and nobody reads the events
+            _ => {
+                backlog.events.clear();
+                backlog.draining = false;
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,367 @@
+// Reestablishing a lost connection. The writer and proxy state are shared with the proxy handles,
+// so they stay valid: every restored proxy gets the same id on the new connection as it had before.
+// The server only accepts ids next to the ones it already knows, so ids that are not restored are
+// reserved with a create_object that fails on purpose. Proxies are told about the connection state
+// without waiting for room in their channels, so one that is not read can not hold up the others.
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    sync::Arc,
+    time::Duration,
+};
+
+use spa::opcode::{DeserializeFromOpCode, MessageOpCode};
+
+use crate::{
+    client::{ClientProxy, UpdateProperties},
+    core_proxy::{self, CoreEvent, CORE_ID},
+    error::DisconnectReason,
+    registry::{self, RegistryEvent, RegistryProxy},
+    remote, socket, PipewireReader, ProxySender,
+};
+
+// Props identifying a global across daemon restarts, the first one the old global has decides.
+// Globals without any of them are matched by object.serial
+const MATCH_KEYS: [&str; 7] = [
+    "object.path",
+    "node.name",
+    "device.name",
+    "metadata.name",
+    "module.name",
+    "factory.name",
+    "client.name",
+];
+
+// How often and how fast to retry connecting, the delay doubles after every failed attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub max_attempts: Option<u32>, // None retries forever
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            max_attempts: None,
+        }
+    }
+}
+
+// What is needed to redo the handshake on a new connection
+#[derive(Debug, Clone)]
+pub(crate) struct Reconnect {
+    pub(crate) policy: ReconnectPolicy,
+    pub(crate) remote: String,
+    pub(crate) version: i32,
+    pub(crate) properties: HashMap<String, String>,
+}
+
+// The global a proxy was bound to, used to find it again after reconnecting
+#[derive(Debug)]
+pub(crate) struct Binding {
+    pub(crate) global: registry::Global,
+    pub(crate) version: i32, // The interface version the proxy was bound with
+}
+
+// Why reconnecting stopped without a connection
+pub(crate) enum Stopped {
+    Closed(tokio::sync::oneshot::Sender<()>), // By PipewireConnection::close
+    GaveUp(DisconnectReason),                 // The policy ran out of attempts
+}
+
+// Reconnect with backoff and restore the proxies, until it succeeds, gives up or is closed
+pub(crate) async fn reconnect(
+    reader: &mut PipewireReader,
+    settings: &Reconnect,
+    mut reason: DisconnectReason,
+) -> Result<(), Stopped> {
+    notify_connection_lost(reader, &reason).await;
+    let mut delay = settings.policy.initial_delay;
+    let mut attempts = 0;
+    loop {
+        match restore(reader, settings, &reason).await {
+            Ok(()) => return Ok(()),
+            Err(e) => reason = DisconnectReason::Error(Arc::new(e)),
+        }
+        attempts += 1;
+        #[cfg(feature = "tracing")]
+        tracing::info!(attempts, %reason, "Reconnecting failed");
+        if settings
+            .policy
+            .max_attempts
+            .is_some_and(|max_attempts| attempts >= max_attempts)
+        {
+            return Err(Stopped::GaveUp(reason));
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => (),
+            closed = crate::wait_for_close(&mut reader.control) => return Err(Stopped::Closed(closed)),
+        }
+        delay = (delay * 2).min(settings.policy.max_delay);
+    }
+}
+
+async fn notify_connection_lost(reader: &PipewireReader, reason: &DisconnectReason) {
+    let (core, senders) = {
+        let mut proxies = reader.proxies.lock().await;
+        // No done events will arrive anymore, dropping the senders makes the pending syncs fail
+        proxies.pending_syncs.clear();
+        proxies.bound_ids.clear();
+        // Generations start over on the new server
+        proxies.generation = 0;
+        proxies.removed_globals.clear();
+        (proxies.core_proxy.clone(), proxies.senders())
+    };
+    if let Some(core) = core {
+        core.send(CoreEvent::ConnectionLost(reason.clone()));
+    }
+    for sender in senders {
+        sender.send_connection_lost(reason.clone());
+    }
+}
+
+// Connect, redo the handshake and recreate the proxies with their old ids
+async fn restore(
+    reader: &mut PipewireReader,
+    settings: &Reconnect,
+    reason: &DisconnectReason,
+) -> io::Result<()> {
+    // A registry of our own for finding the globals the proxies were bound to. The server frees
+    // its id when it is destroyed, after a failed attempt the id is free again right away
+    let registry_id = reader.proxies.lock().await.allocate_id();
+    let result = restore_proxies(reader, settings, reason, registry_id).await;
+    if result.is_err() {
+        reader.proxies.lock().await.free_ids.push(registry_id);
+    }
+    result
+}
+
+async fn restore_proxies(
+    reader: &mut PipewireReader,
+    settings: &Reconnect,
+    reason: &DisconnectReason,
+    registry_id: i32,
+) -> io::Result<()> {
+    let stream = remote::connect(&settings.remote).await?;
+    let (input_stream, output_stream) = stream.into_split();
+    reader.stream = socket::SocketReader::new(input_stream);
+
+    let (placeholders, sync_seq) = {
+        let mut writer = reader.writer.lock().await;
+        writer.replace_stream(output_stream);
+        writer
+            .call_method(
+                CORE_ID,
+                core_proxy::Hello::OP_CODE,
+                core_proxy::Hello {
+                    version: settings.version,
+                },
+            )
+            .await?;
+        writer
+            .call_method(
+                ClientProxy::CLIENT_ID,
+                UpdateProperties::OP_CODE,
+                UpdateProperties {
+                    props: settings.properties.clone(),
+                },
+            )
+            .await?;
+
+        let proxies = reader.proxies.lock().await;
+        let mut placeholders = HashSet::new();
+        for id in 2..=proxies.id_counter {
+            // Our own registry and the ones of the application
+            if id == registry_id || proxies.registry_proxies.contains_key(&id) {
+                writer
+                    .call_method(
+                        CORE_ID,
+                        core_proxy::GetRegistry::OP_CODE,
+                        core_proxy::GetRegistry {
+                            version: RegistryProxy::VERSION,
+                            new_id: id,
+                        },
+                    )
+                    .await?;
+            } else {
+                // There is no factory without a name. The server reports the error on the id and
+                // removes it again, which takes the id into its map of objects, so the ids after
+                // it are accepted. The error and the removal are dropped by read_globals
+                writer
+                    .call_method(
+                        CORE_ID,
+                        core_proxy::CreateObject::OP_CODE,
+                        core_proxy::CreateObject {
+                            factory_name: String::new(),
+                            type_: String::new(),
+                            version: 0,
+                            props: HashMap::new(),
+                            new_id: id,
+                        },
+                    )
+                    .await?;
+                placeholders.insert(id);
+            }
+        }
+        let sync_seq = writer.seq + 1;
+        writer
+            .call_method(
+                CORE_ID,
+                core_proxy::Sync::OP_CODE,
+                core_proxy::Sync {
+                    id: CORE_ID,
+                    seq: sync_seq,
+                },
+            )
+            .await?;
+        (placeholders, sync_seq)
+    };
+
+    let globals = read_globals(reader, registry_id, &placeholders, sync_seq).await?;
+
+    let (core, restored, lost) = {
+        let mut writer = reader.writer.lock().await;
+        let mut proxies = reader.proxies.lock().await;
+        let mut restored = Vec::new();
+        let mut lost = Vec::new();
+        for &id in &placeholders {
+            let Some(sender) = proxies.sender(id) else {
+                // Stays reserved on the server, so it can be used for a new proxy
+                if !proxies.free_ids.contains(&id) {
+                    proxies.free_ids.push(id);
+                }
+                continue;
+            };
+            let global = proxies.bindings.get(&id).and_then(|binding| {
+                let global = globals
+                    .iter()
+                    .find(|global| same_object(&binding.global, global))?;
+                Some((global.clone(), global.version.min(binding.version)))
+            });
+            match global {
+                Some((global, version)) => {
+                    writer
+                        .call_method(
+                            registry_id,
+                            registry::Bind::OP_CODE,
+                            registry::Bind {
+                                id: global.id,
+                                type_: global.type_.clone(),
+                                version,
+                                new_id: id,
+                            },
+                        )
+                        .await?;
+                    if let Some(binding) = proxies.bindings.get_mut(&id) {
+                        binding.global = global;
+                    }
+                    restored.push(sender);
+                }
+                None => {
+                    proxies.remove(id);
+                    proxies.free_ids.push(id);
+                    lost.push(sender);
+                }
+            }
+        }
+        writer
+            .call_method(
+                CORE_ID,
+                core_proxy::Destroy::OP_CODE,
+                core_proxy::Destroy { id: registry_id },
+            )
+            .await?;
+        restored.extend(
+            proxies
+                .client_proxies
+                .get(&ClientProxy::CLIENT_ID)
+                .cloned()
+                .map(ProxySender::Client),
+        );
+        restored.extend(
+            proxies
+                .registry_proxies
+                .values()
+                .cloned()
+                .map(ProxySender::Registry),
+        );
+        (proxies.core_proxy.clone(), restored, lost)
+    };
+
+    #[cfg(feature = "tracing")]
+    tracing::info!(restored = restored.len(), lost = lost.len(), "Reconnected");
+    for sender in lost {
+        sender.send_disconnected(reason.clone());
+    }
+    if let Some(core) = core {
+        core.send(CoreEvent::Reconnected);
+    }
+    for sender in restored {
+        sender.send_reconnected();
+    }
+    Ok(())
+}
+
+// Handle messages until the done for sync_seq, collecting the globals of our own registry.
+// The errors for the reserved ids are expected and not passed on
+async fn read_globals(
+    reader: &mut PipewireReader,
+    registry_id: i32,
+    placeholders: &HashSet<i32>,
+    sync_seq: i32,
+) -> io::Result<Vec<registry::Global>> {
+    let mut globals = Vec::new();
+    loop {
+        let Some(message) = reader.stream.read_message().await? else {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Server closed the connection while restoring the proxies",
+            ));
+        };
+        reader.observe(&message);
+        let opcode = message.header.opcode();
+        let payload = reader.handle_footer(&message.payload).await;
+        if message.header.id == registry_id {
+            if let Ok((_, RegistryEvent::Global(global))) =
+                RegistryEvent::deserialize_from_opcode(opcode, payload)
+            {
+                globals.push(global);
+            }
+            continue;
+        }
+        if message.header.id == CORE_ID {
+            match CoreEvent::deserialize_from_opcode(opcode, payload) {
+                Ok((_, CoreEvent::Done(done))) if done.id == CORE_ID && done.seq == sync_seq => {
+                    return Ok(globals);
+                }
+                Ok((_, CoreEvent::Error(error))) if placeholders.contains(&error.id) => continue,
+                Ok((_, CoreEvent::RemoveId(remove_id))) if placeholders.contains(&remove_id.id) => {
+                    continue
+                }
+                _ => (),
+            }
+        }
+        reader.handle_message(message).await?;
+    }
+}
+
+// Whether new is the same object as old, by its name or otherwise its serial
+fn same_object(old: &registry::Global, new: &registry::Global) -> bool {
+    if old.type_ != new.type_ {
+        return false;
+    }
+    match MATCH_KEYS
+        .iter()
+        .find_map(|key| old.props.get(*key).map(|value| (key, value)))
+    {
+        Some((key, value)) => new.props.get(*key) == Some(value),
+        // Serials are only the same when the daemon did not restart
+        None => old
+            .props
+            .get("object.serial")
+            .is_some_and(|serial| new.props.get("object.serial") == Some(serial)),
+    }
+}
@@ -0,0 +1,159 @@
+// Recording of the messages on a connection, to reproduce a session later with replay::ReplayServer.
+// The file starts with MAGIC, followed by an entry for every message in the order they happened:
+// the direction as one byte (0 sent, 1 received), the time since the first message in microseconds
+// as a little endian u64, the message header as on the wire and the payload.
+// Fds are not recorded, only their count in the header. Replaying passes placeholders instead
+use std::{
+    io::{self, Read, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use zerocopy::{FromBytes, IntoBytes};
+
+use crate::Header;
+
+pub const MAGIC: &[u8; 8] = b"PWREC\0\0\x01";
+
+const HEADER_SIZE: usize = std::mem::size_of::<Header>();
+
+// Seen from the client, Send is a method call and Receive an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Send,
+    Receive,
+}
+
+// Shared by the writer and the reader, so the entries are in the order the messages happened
+pub(crate) type SharedRecorder = Arc<Mutex<Recorder>>;
+
+pub(crate) struct Recorder {
+    output: Box<dyn Write + Send>,
+    start: Option<Instant>, // Set when the first message is recorded, after the magic is written
+}
+
+impl std::fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Recorder")
+            .field("start", &self.start)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Recorder {
+    pub(crate) fn new(output: impl Write + Send + 'static) -> Self {
+        Self {
+            output: Box::new(output),
+            start: None,
+        }
+    }
+
+    // Errors writing the recording are ignored so they never affect the connection
+    pub(crate) fn message(&mut self, direction: Direction, header: &Header, payload: &[u8]) {
+        let _ = self.write_message(direction, header, payload);
+    }
+
+    fn write_message(
+        &mut self,
+        direction: Direction,
+        header: &Header,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                self.output.write_all(MAGIC)?;
+                *self.start.insert(Instant::now())
+            }
+        };
+        let direction: u8 = match direction {
+            Direction::Send => 0,
+            Direction::Receive => 1,
+        };
+        let time = start.elapsed().as_micros() as u64;
+        self.output.write_all(&[direction])?;
+        self.output.write_all(&time.to_le_bytes())?;
+        self.output.write_all(header.as_bytes())?;
+        self.output.write_all(payload)?;
+        self.output.flush()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedMessage {
+    pub direction: Direction,
+    pub time: Duration, // Since the first message of the recording
+    pub id: i32,
+    pub opcode: u32,
+    pub seq: i32,
+    pub n_fds: u32, // The fds themselves are not recorded
+    pub payload: Vec<u8>,
+}
+
+impl RecordedMessage {
+    // The message as it is written to the socket
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let header = Header::new(
+            self.id,
+            self.opcode,
+            self.payload.len() as u32,
+            self.seq,
+            self.n_fds,
+        );
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub messages: Vec<RecordedMessage>,
+}
+
+impl Recording {
+    // Read a recording written by ConnectionBuilder::record
+    pub fn read_from(mut input: impl Read) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut rest = bytes
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or_else(|| invalid("Not a recording, the magic is missing"))?;
+        let mut messages = Vec::new();
+        while !rest.is_empty() {
+            if rest.len() < 1 + 8 + HEADER_SIZE {
+                return Err(invalid("Recording ends in the middle of an entry"));
+            }
+            let direction = match rest[0] {
+                0 => Direction::Send,
+                1 => Direction::Receive,
+                _ => return Err(invalid("Entry has an unknown direction")),
+            };
+            let time = u64::from_le_bytes(rest[1..9].try_into().unwrap());
+            let header = Header::read_from_bytes(&rest[9..9 + HEADER_SIZE])
+                .expect("Length of byte slice must be equal to header size");
+            rest = &rest[9 + HEADER_SIZE..];
+            if rest.len() < header.size() {
+                return Err(invalid("Recording ends in the middle of a payload"));
+            }
+            let (payload, remaining) = rest.split_at(header.size());
+            rest = remaining;
+            messages.push(RecordedMessage {
+                direction,
+                time: Duration::from_micros(time),
+                id: header.id,
+                opcode: header.opcode(),
+                seq: header.seq,
+                n_fds: header.n_fds,
+                payload: payload.to_vec(),
+            });
+        }
+        Ok(Self { messages })
+    }
+}
@@ -1,51 +1,98 @@
-use std::{collections::HashMap, ops::{Deref, DerefMut}, sync::Arc};
+use std::collections::HashMap;
 
 use spa::{deserialize::{DeserializeError, PodDeserializer}, opcode::{self, MessageOpCode}, serialize::PodSerializer};
 use spa_derive::{opcode, PodDeserialize, PodSerialize};
-use tokio::sync::Mutex;
+use tokio::io;
 
-use crate::{ core_proxy, proxy::Proxy, PipewireWriter};
+use crate::{
+    proxy::{self, BindableProxy},
+    reconnect,
+};
 
-pub struct RegistryProxy {
-    id: i32,
-    connection: Arc<Mutex<PipewireWriter>>,
-    event_receiver: tokio::sync::mpsc::Receiver<RegistryEvent>,
+proxy::define_proxy! {
+    pub struct RegistryProxy: RegistryEvent {}
 }
 
 impl RegistryProxy {
     pub(crate) const VERSION: i32 = 3; // Version of the registry interface used
-    pub(crate) fn new(id: i32, connection: Arc<Mutex<PipewireWriter>>, event_receiver: tokio::sync::mpsc::Receiver<RegistryEvent>) -> RegistryProxy{
-        RegistryProxy {id, connection, event_receiver }
-    }
-}
-
-impl Proxy for RegistryProxy {
-    type Event = RegistryEvent;
-
-    fn id(&self) -> i32 {
-        self.id
-    }
-
-    fn get_channel(&mut self) ->  &mut tokio::sync::mpsc::Receiver<Self::Event> {
-        &mut self.event_receiver
-    }
-
-    fn get_connection(&self) -> std::sync::Arc<tokio::sync::Mutex<crate::PipewireWriter>> {
-        self.connection.clone()
-    }
-}
-
-impl Deref for RegistryProxy {
-    type Target = tokio::sync::mpsc::Receiver<RegistryEvent>;
 
-    fn deref(&self) -> &Self::Target {
-        &self.event_receiver
+    // Bind to a global, creating a proxy of type P for it.
+    // The interface version used is the lowest of what the global and P supports
+    pub async fn bind<P: BindableProxy>(&mut self, global: &Global) -> io::Result<P> {
+        if global.type_ != P::TYPE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Global {} has type {}, but the proxy has type {}",
+                    global.id,
+                    global.type_,
+                    P::TYPE
+                ),
+            ));
+        }
+        let (id, receiver) = {
+            let mut connection = self.connection.lock().await;
+            let mut proxies = self.proxies.lock().await;
+            if proxies.is_stale(global) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Global {} was removed", global.id),
+                ));
+            }
+            let (sender, receiver) =
+                tokio::sync::mpsc::channel(P::channel_capacity(&proxies.capacities));
+            let id = proxies.allocate_id();
+            P::register(&mut proxies, id, sender);
+            proxies.bindings.insert(
+                id,
+                reconnect::Binding {
+                    global: global.clone(),
+                    version: global.version.min(P::VERSION),
+                },
+            );
+            // The server refuses the bind if the id now belongs to a global newer than this one
+            connection.next_generation = Some(global.generation);
+            let result = connection
+                .call_method(
+                    self.id,
+                    Bind::OP_CODE,
+                    Bind {
+                        id: global.id,
+                        type_: P::TYPE.to_string(),
+                        version: global.version.min(P::VERSION),
+                        new_id: id,
+                    },
+                )
+                .await;
+            if let Err(e) = result {
+                proxies.unregister(id);
+                return Err(e);
+            }
+            (id, receiver)
+        };
+
+        Ok(P::new(
+            id,
+            self.connection.clone(),
+            receiver,
+            self.proxies.clone(),
+        ))
     }
-}
 
-impl DerefMut for RegistryProxy {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.event_receiver
+    // Ask the server to destroy the global with the given id, this requires the X permission on it
+    pub async fn destroy_global(&self, global_id: i32) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked(
+                &self.proxies,
+                self.id,
+                Destroy::OP_CODE,
+                Destroy { id: global_id },
+            )
+            .await?;
+        pending_sync.await
     }
 }
 
@@ -66,15 +113,15 @@ pub struct Destroy {
 }
 
 // === Events ===
-#[derive(Debug)]
-pub enum RegistryEvent {
-    Global(Global),
-    GlobalRemove(GlobalRemove),
-    // Added to allow for receiving Done events on all proxies
-    Done(core_proxy::Done)
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum RegistryEvent {
+        Global(Global),
+        GlobalRemove(GlobalRemove),
+    }
 }
 
-#[derive(PodSerialize, PodDeserialize, Debug)]
+#[derive(PodSerialize, PodDeserialize, Debug, Clone)]
 #[opcode(0)]
 pub struct Global {
     pub id: i32,
@@ -82,6 +129,34 @@ pub struct Global {
     pub type_: String,
     pub version: i32,
     pub props: HashMap<String, String>,
+    // The registry generation the global was received in, not part of the message
+    #[pod(skip)]
+    pub(crate) generation: u64,
+}
+
+impl Global {
+    pub fn new(
+        id: i32,
+        permissions: i32,
+        type_: &str,
+        version: i32,
+        props: HashMap<String, String>,
+    ) -> Global {
+        Global {
+            id,
+            permissions,
+            type_: type_.to_string(),
+            version,
+            props,
+            generation: 0,
+        }
+    }
+
+    // The registry generation the global was received in, binding it fails once a newer global
+    // has taken its id
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 }
 
 #[derive(PodSerialize, PodDeserialize, Debug)]
@@ -93,21 +168,21 @@ pub struct GlobalRemove {
 
 impl opcode::DeserializeFromOpCode for RegistryEvent {
 
-    fn deserialize_from_opcode<'de>(
+    fn deserialize_from_opcode(
         opcode: u32,
-        buffer: &'de [u8],
-    ) -> Result<(&'de [u8], Self), spa::deserialize::DeserializeError<&'de [u8]>>
+        buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
     where
         Self: Sized,
     {
         match opcode {
             Global::OP_CODE => {
-                let (remain, value): (&[u8], Global) = PodDeserializer::deserialize_from(&buffer)?;
+                let (remain, value): (&[u8], Global) = PodDeserializer::deserialize_from(buffer)?;
                 Ok((remain, RegistryEvent::Global(value)))
             }
             GlobalRemove::OP_CODE => {
                 let (remain, value): (&[u8], GlobalRemove) =
-                    PodDeserializer::deserialize_from(&buffer)?;
+                    PodDeserializer::deserialize_from(buffer)?;
                 Ok((remain, RegistryEvent::GlobalRemove(value)))
             }
             _ => Err(DeserializeError::InvalidType),
@@ -119,14 +194,19 @@ impl core::fmt::Display for RegistryEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             RegistryEvent::Global(global) => {
-                write!(f, "Id {}, type {}\n", global.id, global.type_)?;
+                writeln!(f, "Id {}, type {}", global.id, global.type_)?;
                 for (key, value) in global.props.iter() {
-                    write!(f, "\t{} {} \n", key, value)?;
+                    writeln!(f, "\t{} {} ", key, value)?;
                 }
-                write!(f, "")
+                Ok(())
             },
-            RegistryEvent::GlobalRemove(global_remove) => Ok(()),
-            RegistryEvent::Done(done) => Ok(()),
+            RegistryEvent::GlobalRemove(_) => Ok(()),
+            RegistryEvent::Done(_) => Ok(()),
+            RegistryEvent::Error(error) => writeln!(f, "{}", error),
+            RegistryEvent::ConnectionLost(reason) | RegistryEvent::Disconnected(reason) => {
+                writeln!(f, "{}", reason)
+            }
+            RegistryEvent::Reconnected => Ok(()),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,150 @@
+// Resolving a remote name to the socket paths to connect to, following libpipewire.
+// A remote is either a single name or a list like "[pipewire-0-manager,pipewire-0]",
+// where every entry is tried in order. Relative names are looked up in the runtime dir.
+use std::{
+    collections::HashMap,
+    env, io,
+    path::{Path, PathBuf},
+};
+
+use tokio::net::UnixStream;
+
+pub const DEFAULT_REMOTE: &str = "pipewire-0";
+// The manager socket grants more permissions, managers fall back to the default one
+pub const DEFAULT_MANAGER_REMOTE: &str = "[pipewire-0-manager,pipewire-0]";
+
+// Client property saying what the client connects for, "manager" uses DEFAULT_MANAGER_REMOTE
+pub const KEY_REMOTE_INTENTION: &str = "remote.intention";
+
+// Checked in order, same as libpipewire
+const RUNTIME_DIR_VARS: [&str; 3] = ["PIPEWIRE_RUNTIME_DIR", "XDG_RUNTIME_DIR", "USERPROFILE"];
+
+// The remote from PIPEWIRE_REMOTE, or the default for the client properties when it is unset or empty
+pub(crate) fn default_remote(properties: &HashMap<String, String>) -> String {
+    match env::var("PIPEWIRE_REMOTE") {
+        Ok(remote) if !remote.is_empty() => remote,
+        _ => intended_remote(properties).to_string(),
+    }
+}
+
+// Like libpipewire, only clients that say they are a manager try the manager socket
+fn intended_remote(properties: &HashMap<String, String>) -> &'static str {
+    match properties.get(KEY_REMOTE_INTENTION).map(String::as_str) {
+        Some("manager") => DEFAULT_MANAGER_REMOTE,
+        _ => DEFAULT_REMOTE,
+    }
+}
+
+fn runtime_dir() -> Option<PathBuf> {
+    RUNTIME_DIR_VARS
+        .iter()
+        .filter_map(env::var_os)
+        .find(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+// Split "[a, b]" or "[ \"a\" \"b\" ]" into its entries, anything else is a single name
+fn remote_names(remote: &str) -> Vec<&str> {
+    let remote = remote.trim();
+    match remote
+        .strip_prefix('[')
+        .and_then(|list| list.strip_suffix(']'))
+    {
+        Some(list) => list
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .map(|name| name.trim_matches('"'))
+            .filter(|name| !name.is_empty())
+            .collect(),
+        None => vec![remote],
+    }
+}
+
+fn socket_paths(remote: &str) -> io::Result<Vec<PathBuf>> {
+    let names = remote_names(remote);
+    if names.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Remote {:?} does not contain any socket names", remote),
+        ));
+    }
+    let runtime_dir = runtime_dir();
+    names
+        .into_iter()
+        .map(|name| {
+            if Path::new(name).is_absolute() {
+                return Ok(PathBuf::from(name));
+            }
+            match &runtime_dir {
+                Some(dir) => Ok(dir.join(name)),
+                None => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "Cannot resolve remote {:?}, none of {} is set",
+                        name,
+                        RUNTIME_DIR_VARS.join(", ")
+                    ),
+                )),
+            }
+        })
+        .collect()
+}
+
+// Connect to the first socket of the remote that accepts the connection
+pub(crate) async fn connect(remote: &str) -> io::Result<UnixStream> {
+    let mut tried = Vec::new();
+    let mut kind = io::ErrorKind::NotFound;
+    for path in socket_paths(remote)? {
+        match UnixStream::connect(&path).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => {
+                kind = e.kind();
+                tried.push(format!("{} ({})", path.display(), e));
+            }
+        }
+    }
+    Err(io::Error::new(
+        kind,
+        format!(
+            "Could not connect to remote {:?}, tried: {}",
+            remote,
+            tried.join(", ")
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn managers_use_the_manager_socket() {
+        let mut properties = HashMap::new();
+        assert_eq!(intended_remote(&properties), DEFAULT_REMOTE);
+        properties.insert(KEY_REMOTE_INTENTION.to_string(), "generic".to_string());
+        assert_eq!(intended_remote(&properties), DEFAULT_REMOTE);
+        properties.insert(KEY_REMOTE_INTENTION.to_string(), "manager".to_string());
+        assert_eq!(intended_remote(&properties), DEFAULT_MANAGER_REMOTE);
+    }
+
+    #[test]
+    fn remote_lists_are_split() {
+        assert_eq!(remote_names(" pipewire-0 "), vec!["pipewire-0"]);
+        assert_eq!(
+            remote_names(DEFAULT_MANAGER_REMOTE),
+            vec!["pipewire-0-manager", "pipewire-0"]
+        );
+        assert_eq!(remote_names("[ \"a\" \"b\",c ]"), vec!["a", "b", "c"]);
+        assert!(remote_names("[]").is_empty());
+    }
+
+    #[test]
+    fn absolute_paths_are_used_as_they_are() {
+        let paths = socket_paths("[/run/a,/run/b]").unwrap();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("/run/a"), PathBuf::from("/run/b")]
+        );
+        let error = socket_paths("[ ]").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
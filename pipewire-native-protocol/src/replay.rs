@@ -0,0 +1,114 @@
+// Replaying the server side of a recording, so the client code of a session can be rerun without
+// the daemon and hardware it was recorded with. The recorded events are sent in order, each one
+// as soon as the methods recorded before it was called. Timestamps are not used, the pace is
+// set by the client, which keeps the replay deterministic
+use std::{
+    fs::File,
+    os::fd::{AsFd, OwnedFd},
+};
+
+use tokio::{
+    io,
+    net::{unix::OwnedWriteHalf, UnixStream},
+    sync::oneshot,
+};
+
+use crate::{
+    client::ClientProxy,
+    core_proxy::CoreProxy,
+    record::{Direction, RecordedMessage, Recording},
+    socket, PipewireConnection,
+};
+
+pub struct ReplayServer {
+    result: oneshot::Receiver<io::Result<()>>,
+}
+
+impl ReplayServer {
+    // Start replaying, the returned stream is the client end of the connection
+    pub fn new(recording: Recording) -> io::Result<(ReplayServer, UnixStream)> {
+        let (client, server) = UnixStream::pair()?;
+        let (sender, result) = oneshot::channel();
+        tokio::spawn(async move {
+            let (input_stream, output_stream) = server.into_split();
+            let mut reader = socket::SocketReader::new(input_stream);
+            let _ = sender.send(replay(&mut reader, &output_stream, recording).await);
+            // Keep the connection open until the client is done, what it sends is not checked anymore
+            while let Ok(Some(_)) = reader.read_message().await {}
+        });
+        Ok((ReplayServer { result }, client))
+    }
+
+    // Start replaying and connect a client to it with the default configuration
+    pub async fn connect(
+        recording: Recording,
+    ) -> io::Result<(ReplayServer, CoreProxy, ClientProxy)> {
+        let (server, stream) = Self::new(recording)?;
+        let (core, client) = PipewireConnection::connect(stream).await?;
+        Ok((server, core, client))
+    }
+
+    // Wait until the whole recording is replayed. Fails if the client called other methods than
+    // the recorded ones, or closed the connection before the end of the recording
+    pub async fn finished(self) -> io::Result<()> {
+        self.result
+            .await
+            .unwrap_or_else(|_| Err(io::Error::other("Replay stopped without a result")))
+    }
+}
+
+async fn replay(
+    reader: &mut socket::SocketReader,
+    output_stream: &OwnedWriteHalf,
+    recording: Recording,
+) -> io::Result<()> {
+    for (index, recorded) in recording.messages.iter().enumerate() {
+        match recorded.direction {
+            Direction::Send => expect_method(reader, index, recorded).await?,
+            Direction::Receive => {
+                let placeholders = placeholder_fds(recorded.n_fds)?;
+                let fds: Vec<_> = placeholders.iter().map(|fd| fd.as_fd()).collect();
+                socket::write_message(output_stream, &recorded.to_bytes(), &fds).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Only the proxy id and opcode are compared, payloads contain things like the process id
+async fn expect_method(
+    reader: &mut socket::SocketReader,
+    index: usize,
+    recorded: &RecordedMessage,
+) -> io::Result<()> {
+    let Some(message) = reader.read_message().await? else {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Client closed the connection, expected opcode {} on id {} (message {})",
+                recorded.opcode, recorded.id, index
+            ),
+        ));
+    };
+    if message.header.id != recorded.id || message.header.opcode() != recorded.opcode {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Client called opcode {} on id {}, expected opcode {} on id {} (message {})",
+                message.header.opcode(),
+                message.header.id,
+                recorded.opcode,
+                recorded.id,
+                index
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// Stand-ins for the fds that was sent with a recorded event
+fn placeholder_fds(n_fds: u32) -> io::Result<Vec<OwnedFd>> {
+    (0..n_fds)
+        .map(|_| File::open("/dev/null").map(OwnedFd::from))
+        .collect()
+}
@@ -0,0 +1,82 @@
+// Creating sockets for sandboxed clients, like Flatpak does. The server listens on a socket we
+// created and gives the clients that connect to it the properties of the security context, which
+// restrict what they can access. The server stops listening when the close fd is hung up
+use std::{collections::HashMap, os::fd::BorrowedFd};
+
+use spa::{
+    deserialize::DeserializeError,
+    opcode::{self, MessageOpCode},
+    serialize::PodSerializer,
+    value::Fd,
+};
+use spa_derive::{opcode, PodDeserialize, PodSerialize};
+use tokio::io;
+
+use crate::proxy;
+
+proxy::define_proxy! {
+    pub struct SecurityContextProxy: SecurityContextEvent {}
+    bindable("PipeWire:Interface:SecurityContext", 3, security_context, security_context_proxies)
+}
+
+impl SecurityContextProxy {
+    // Make the server accept clients on listen_fd, a socket that is bound and listening.
+    // The server keeps accepting until close_fd is closed, usually the read end of a pipe whose
+    // write end the sandbox keeps open. The props, e.g. pipewire.sec.engine and pipewire.access,
+    // are added to the clients that connect and can not be changed by them
+    pub async fn create(
+        &self,
+        listen_fd: BorrowedFd<'_>,
+        close_fd: BorrowedFd<'_>,
+        props: HashMap<String, String>,
+    ) -> io::Result<()> {
+        let pending_sync = self
+            .connection
+            .lock()
+            .await
+            .call_method_checked_with_fds(
+                &self.proxies,
+                self.id,
+                Create::OP_CODE,
+                Create {
+                    listen_fd: Fd(0),
+                    close_fd: Fd(1),
+                    props,
+                },
+                &[listen_fd, close_fd],
+            )
+            .await?;
+        pending_sync.await
+    }
+}
+
+// === Methods ===
+
+// The fds are indices into the fds sent with the message, listen_fd first
+#[derive(PodSerialize, PodDeserialize, Debug)]
+#[opcode(1)]
+pub struct Create {
+    pub listen_fd: Fd,
+    pub close_fd: Fd,
+    pub props: HashMap<String, String>,
+}
+
+// === Events ===
+proxy::define_events! {
+    #[derive(Debug)]
+    pub enum SecurityContextEvent {
+    }
+}
+
+// No events for security context
+impl opcode::DeserializeFromOpCode for SecurityContextEvent {
+    fn deserialize_from_opcode(
+        _opcode: u32,
+        _buffer: &[u8],
+    ) -> Result<(&[u8], Self), spa::deserialize::DeserializeError<&[u8]>>
+    where
+        Self: Sized,
+    {
+        Err(DeserializeError::InvalidType)
+    }
+}
@@ -0,0 +1,253 @@
+// Reading and writing of messages on the native protocol socket.
+// File descriptors are passed along with the messages as SCM_RIGHTS ancillary data,
+// which is why we use sendmsg/recvmsg directly instead of the AsyncRead/AsyncWrite traits.
+use std::{
+    collections::VecDeque,
+    io::{self, IoSlice, IoSliceMut},
+    os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+};
+
+use nix::sys::socket::{recvmsg, sendmsg, ControlMessage, ControlMessageOwned, MsgFlags};
+use tokio::{
+    io::Interest,
+    net::unix::{OwnedReadHalf, OwnedWriteHalf},
+};
+use tokio_util::bytes::{Buf, BytesMut};
+use zerocopy::FromBytes;
+
+use crate::Header;
+
+// Maximum number of fds that can be attached to a single message, same limit as libpipewire
+pub(crate) const MAX_FDS: usize = 28;
+const HEADER_SIZE: usize = std::mem::size_of::<Header>();
+const READ_SIZE: usize = 4096;
+
+// A single message read from the socket, together with the fds that was sent with it
+pub(crate) struct ReceivedMessage {
+    pub header: Header,
+    pub payload: BytesMut,
+    pub fds: Vec<OwnedFd>,
+}
+
+pub(crate) struct SocketReader {
+    stream: OwnedReadHalf,
+    buffer: BytesMut,
+    // Fds are received out of band, so we queue them until the message that owns them is complete
+    fds: VecDeque<OwnedFd>,
+}
+
+impl SocketReader {
+    pub(crate) fn new(stream: OwnedReadHalf) -> Self {
+        Self {
+            stream,
+            buffer: BytesMut::with_capacity(READ_SIZE),
+            fds: VecDeque::new(),
+        }
+    }
+
+    // Read the next message from the socket, returns None when the socket was closed
+    pub(crate) async fn read_message(&mut self) -> io::Result<Option<ReceivedMessage>> {
+        loop {
+            if let Some(message) = self.next_buffered_message()? {
+                return Ok(Some(message));
+            }
+            if self.fill_buffer().await? == 0 {
+                return if self.buffer.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Socket closed in the middle of a message",
+                    ))
+                };
+            }
+        }
+    }
+
+    fn next_buffered_message(&mut self) -> io::Result<Option<ReceivedMessage>> {
+        if self.buffer.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+        let header = Header::read_from_bytes(&self.buffer[..HEADER_SIZE])
+            .expect("Length of byte slice must be equal to header size");
+        if self.buffer.len() < HEADER_SIZE + header.size() {
+            return Ok(None);
+        }
+        let n_fds = header.n_fds as usize;
+        if self.fds.len() < n_fds {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Message has more fds than was received on the socket",
+            ));
+        }
+        self.buffer.advance(HEADER_SIZE);
+        let payload = self.buffer.split_to(header.size());
+        let fds = self.fds.drain(..n_fds).collect();
+        Ok(Some(ReceivedMessage {
+            header,
+            payload,
+            fds,
+        }))
+    }
+
+    async fn fill_buffer(&mut self) -> io::Result<usize> {
+        let socket = self.stream.as_ref();
+        let mut chunk = [0u8; READ_SIZE];
+        loop {
+            socket.readable().await?;
+            let result = socket.try_io(Interest::READABLE, || {
+                let mut iov = [IoSliceMut::new(&mut chunk)];
+                let mut cmsg_buffer = nix::cmsg_space!([RawFd; MAX_FDS]);
+                let msg = recvmsg::<()>(
+                    socket.as_raw_fd(),
+                    &mut iov,
+                    Some(&mut cmsg_buffer),
+                    MsgFlags::MSG_CMSG_CLOEXEC,
+                )?;
+                // The fds that did not fit are closed by the kernel, the message they belong to can
+                // not be handled anymore. The connection fails, so nothing is read after it
+                if msg.flags.contains(MsgFlags::MSG_CTRUNC) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Received more fds than fit in the control buffer, some were lost",
+                    ));
+                }
+                let mut fds = Vec::new();
+                for cmsg in msg.cmsgs()? {
+                    if let ControlMessageOwned::ScmRights(received) = cmsg {
+                        // SAFETY: The kernel just installed these fds in our process, nothing else owns them
+                        fds.extend(
+                            received
+                                .into_iter()
+                                .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }),
+                        );
+                    }
+                }
+                Ok((msg.bytes, fds))
+            });
+            match result {
+                Ok((read, fds)) => {
+                    self.buffer.extend_from_slice(&chunk[..read]);
+                    self.fds.extend(fds);
+                    return Ok(read);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// Write a complete message to the socket, the fds are attached to the first chunk that is sent
+pub(crate) async fn write_message(
+    stream: &OwnedWriteHalf,
+    bytes: &[u8],
+    fds: &[BorrowedFd<'_>],
+) -> io::Result<()> {
+    if fds.len() > MAX_FDS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Cannot send more than {} fds with a message", MAX_FDS),
+        ));
+    }
+    let raw_fds: Vec<RawFd> = fds.iter().map(|fd| fd.as_raw_fd()).collect();
+    let socket = stream.as_ref();
+    let mut written = 0;
+    while written < bytes.len() {
+        socket.writable().await?;
+        let fds: &[RawFd] = if written == 0 { &raw_fds } else { &[] };
+        let result = socket.try_io(Interest::WRITABLE, || {
+            let iov = [IoSlice::new(&bytes[written..])];
+            let scm_rights = [ControlMessage::ScmRights(fds)];
+            let cmsgs: &[ControlMessage] = if fds.is_empty() { &[] } else { &scm_rights };
+            Ok(sendmsg::<()>(
+                socket.as_raw_fd(),
+                &iov,
+                cmsgs,
+                MsgFlags::MSG_NOSIGNAL,
+                None,
+            )?)
+        });
+        match result {
+            Ok(sent) => written += sent,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, io::Write, os::fd::AsFd, os::unix::net::UnixStream as StdUnixStream};
+
+    use tokio::net::UnixStream;
+    use zerocopy::IntoBytes;
+
+    use super::*;
+
+    fn message(id: i32, payload: &[u8], n_fds: u32) -> Vec<u8> {
+        let header = Header::new(id, 1, payload.len() as u32, 0, n_fds);
+        [header.as_bytes(), payload].concat()
+    }
+
+    #[tokio::test]
+    async fn passes_fds_with_messages() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let (client_read, _client_write) = client.into_split();
+        let (_server_read, server_write) = server.into_split();
+        let mut reader = SocketReader::new(client_read);
+        let (mut passed, mut kept) = StdUnixStream::pair().unwrap();
+
+        write_message(&server_write, &message(2, &[1; 8], 0), &[])
+            .await
+            .unwrap();
+        write_message(&server_write, &message(3, &[2; 16], 1), &[passed.as_fd()])
+            .await
+            .unwrap();
+
+        let first = reader.read_message().await.unwrap().unwrap();
+        assert_eq!(first.header.id, 2);
+        assert_eq!(&first.payload[..], &[1; 8]);
+        assert!(first.fds.is_empty());
+
+        let second = reader.read_message().await.unwrap().unwrap();
+        assert_eq!(second.header.id, 3);
+        assert_eq!(&second.payload[..], &[2; 16]);
+        assert_eq!(second.fds.len(), 1);
+
+        // The received fd refers to the same socket as the one that was sent
+        let mut received = StdUnixStream::from(second.fds.into_iter().next().unwrap());
+        received.write_all(b"fd").unwrap();
+        let mut buffer = [0; 2];
+        kept.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"fd");
+        passed.write_all(b"ok").unwrap();
+        kept.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"ok");
+    }
+
+    #[tokio::test]
+    async fn fails_when_fds_are_truncated() {
+        let (client, server) = StdUnixStream::pair().unwrap();
+        client.set_nonblocking(true).unwrap();
+        let (client_read, _client_write) = UnixStream::from_std(client).unwrap().into_split();
+        let mut reader = SocketReader::new(client_read);
+
+        // More fds than fit in the control buffer, which write_message refuses to send
+        let (passed, _kept) = StdUnixStream::pair().unwrap();
+        let fds = vec![passed.as_raw_fd(); MAX_FDS + 4];
+        let bytes = message(2, &[0; 8], fds.len() as u32);
+        sendmsg::<()>(
+            server.as_raw_fd(),
+            &[IoSlice::new(&bytes)],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )
+        .unwrap();
+
+        let error = reader.read_message().await.err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
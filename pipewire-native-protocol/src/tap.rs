@@ -0,0 +1,316 @@
+// A dump of every message sent and received on a connection, similar to the mod.protocol debug
+// output of libpipewire. Messages are decoded as generic pods and printed as a tree, together with
+// the interface and method or event name of the opcode.
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use spa::{
+    deserialize::PodDeserializer,
+    opcode::MessageOpCode,
+    value::{Object, Value},
+};
+
+use crate::{
+    client::ClientProxy,
+    core_proxy::{CreateObject, GetRegistry, RemoveId, CORE_ID},
+    footer,
+    record::Direction,
+    registry::Bind,
+    Header,
+};
+
+// Method and event names of an interface with their opcodes
+struct Interface {
+    name: &'static str,
+    methods: &'static [(u32, &'static str)],
+    events: &'static [(u32, &'static str)],
+}
+
+// Opcodes and names taken from the message types, so they can not drift apart. The type name is
+// used unless the protocol name is given after `as`
+macro_rules! names {
+    (@name $message:ident) => {
+        stringify!($message)
+    };
+    (@name $message:ident $name:literal) => {
+        $name
+    };
+    ($module:ident: $($message:ident $(as $name:literal)?),* $(,)?) => {
+        &[$((
+            <crate::$module::$message as MessageOpCode>::OP_CODE,
+            names!(@name $message $($name)?),
+        )),*]
+    };
+}
+
+// Method 0 of every interface, it has no type as the library never sends it
+const ADD_LISTENER: u32 = 0;
+
+static INTERFACES: [Interface; 13] = [
+    Interface {
+        name: "Core",
+        methods: names!(core_proxy: Hello, Sync, Pong, ErrorMethod as "Error", GetRegistry,
+            CreateObject, Destroy),
+        events: names!(core_proxy: Info, Done, Ping, ErrorEvent as "Error", RemoveId, BoundId,
+            AddMem, RemoveMem, BoundProps),
+    },
+    Interface {
+        name: "Registry",
+        methods: names!(registry: Bind, Destroy),
+        events: names!(registry: Global, GlobalRemove),
+    },
+    Interface {
+        name: "Client",
+        methods: names!(client: Error, UpdateProperties, GetPermissions, UpdatePermissions),
+        events: names!(client: Info, Permissions),
+    },
+    Interface {
+        name: "Device",
+        methods: names!(device: SubscribeParams, EnumParams, SetParam),
+        events: names!(device: Info, Param),
+    },
+    Interface {
+        name: "Factory",
+        methods: &[],
+        events: names!(factory: Info),
+    },
+    Interface {
+        name: "Link",
+        methods: &[],
+        events: names!(link: Info),
+    },
+    Interface {
+        name: "Module",
+        methods: &[],
+        events: names!(module: Info),
+    },
+    Interface {
+        name: "Node",
+        methods: names!(node: SubscribeParams, EnumParams, SetParam, SendCommand),
+        events: names!(node: Info, Param),
+    },
+    Interface {
+        name: "Port",
+        methods: names!(port: SubscribeParams, EnumParams),
+        events: names!(port: Info, Param),
+    },
+    Interface {
+        name: "ClientNode",
+        methods: names!(client_node: GetNode, Update, PortUpdate, SetActive,
+            EventMethod as "Event", PortBuffers),
+        events: names!(client_node: Transport, SetParam, SetIO, Event, Command, AddPort,
+            RemovePort, PortSetParam, UseBuffers as "PortUseBuffers", PortSetIO, SetActivation,
+            PortSetMixInfo),
+    },
+    Interface {
+        name: "Metadata",
+        methods: names!(metadata: SetProperty, Clear),
+        events: names!(metadata: Property),
+    },
+    Interface {
+        name: "Profiler",
+        methods: &[],
+        events: names!(profiler: Profile),
+    },
+    Interface {
+        name: "SecurityContext",
+        methods: names!(security_context: Create),
+        events: &[],
+    },
+];
+
+// Core methods and events that create or remove ids, the tap follows them to know the interfaces
+const CORE_GET_REGISTRY: u32 = GetRegistry::OP_CODE;
+const CORE_CREATE_OBJECT: u32 = CreateObject::OP_CODE;
+const CORE_REMOVE_ID: u32 = RemoveId::OP_CODE;
+const REGISTRY_BIND: u32 = Bind::OP_CODE;
+
+// Shared by the writer and the reader, so the dump shows the messages in the order they happened
+pub(crate) type SharedTap = Arc<Mutex<MessageTap>>;
+
+pub(crate) struct MessageTap {
+    output: Box<dyn io::Write + Send>,
+    interfaces: HashMap<i32, &'static Interface>, // Interface of every id, learned from the messages
+}
+
+impl std::fmt::Debug for MessageTap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageTap").finish_non_exhaustive()
+    }
+}
+
+impl MessageTap {
+    pub(crate) fn new(output: impl io::Write + Send + 'static) -> Self {
+        let mut interfaces = HashMap::new();
+        interfaces.insert(CORE_ID, &INTERFACES[0]);
+        interfaces.insert(ClientProxy::CLIENT_ID, &INTERFACES[2]);
+        Self {
+            output: Box::new(output),
+            interfaces,
+        }
+    }
+
+    // Write a message, errors writing the dump are ignored so they never affect the connection
+    pub(crate) fn message(&mut self, direction: Direction, header: &Header, payload: &[u8]) {
+        let (message, footer) = footer::split(payload);
+        let pod = PodDeserializer::deserialize_any_from(message)
+            .ok()
+            .map(|(_, pod)| pod);
+        let footer = PodDeserializer::deserialize_any_from(footer)
+            .ok()
+            .map(|(_, pod)| pod);
+        let _ = self.write_message(direction, header, payload, pod.as_ref(), footer.as_ref());
+        if let Some(pod) = &pod {
+            self.follow_ids(direction, header, pod);
+        }
+    }
+
+    fn write_message(
+        &mut self,
+        direction: Direction,
+        header: &Header,
+        payload: &[u8],
+        pod: Option<&Value>,
+        footer: Option<&Value>,
+    ) -> io::Result<()> {
+        let (arrow, verb) = match direction {
+            Direction::Send => (">>>>>>>>>", "send"),
+            Direction::Receive => ("<<<<<<<<<", "recv"),
+        };
+        let opcode = header.opcode();
+        let name = match self.interfaces.get(&header.id) {
+            Some(interface) => {
+                let names = match direction {
+                    Direction::Send if opcode == ADD_LISTENER => &[(ADD_LISTENER, "AddListener")],
+                    Direction::Send => interface.methods,
+                    Direction::Receive => interface.events,
+                };
+                match names.iter().find(|(op, _)| *op == opcode) {
+                    Some((_, name)) => format!("{}.{}", interface.name, name),
+                    None => format!("{}.<unknown>", interface.name),
+                }
+            }
+            None => "<unknown>".to_string(),
+        };
+        writeln!(
+            self.output,
+            "{} {} {} id:{} op:{} size:{} seq:{} fds:{}",
+            arrow,
+            verb,
+            name,
+            header.id,
+            opcode,
+            header.size(),
+            header.seq,
+            header.n_fds
+        )?;
+        match pod {
+            Some(pod) => write_pod(&mut self.output, pod, 1)?,
+            None => writeln!(self.output, "  <invalid pod> {:02x?}", payload)?,
+        }
+        if let Some(footer) = footer {
+            writeln!(self.output, "  Footer")?;
+            write_pod(&mut self.output, footer, 2)?;
+        }
+        self.output.flush()
+    }
+
+    fn follow_ids(&mut self, direction: Direction, header: &Header, pod: &Value) {
+        let Value::Struct(fields) = pod else {
+            return;
+        };
+        let opcode = header.opcode();
+        let interface = self.interfaces.get(&header.id).map(|i| i.name);
+        match (direction, interface, opcode) {
+            (Direction::Send, Some("Core"), CORE_GET_REGISTRY) => {
+                if let Some(Value::Int(new_id)) = fields.get(1) {
+                    self.interfaces.insert(*new_id, &INTERFACES[1]);
+                }
+            }
+            (Direction::Send, Some("Core"), CORE_CREATE_OBJECT) => {
+                if let (Some(Value::String(type_)), Some(Value::Int(new_id))) =
+                    (fields.get(1), fields.get(4))
+                {
+                    self.insert(*new_id, type_);
+                }
+            }
+            (Direction::Send, Some("Registry"), REGISTRY_BIND) => {
+                if let (Some(Value::String(type_)), Some(Value::Int(new_id))) =
+                    (fields.get(1), fields.get(3))
+                {
+                    self.insert(*new_id, type_);
+                }
+            }
+            (Direction::Receive, Some("Core"), CORE_REMOVE_ID) => {
+                if let Some(Value::Int(id)) = fields.first() {
+                    self.interfaces.remove(id);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Remember the interface for a type like "PipeWire:Interface:Node"
+    fn insert(&mut self, id: i32, type_: &str) {
+        let name = type_.strip_prefix("PipeWire:Interface:").unwrap_or(type_);
+        let interface = INTERFACES.iter().find(|interface| interface.name == name);
+        match interface {
+            Some(interface) => self.interfaces.insert(id, interface),
+            None => self.interfaces.remove(&id),
+        };
+    }
+}
+
+fn write_pod(output: &mut dyn io::Write, pod: &Value, depth: usize) -> io::Result<()> {
+    let indent = "  ".repeat(depth);
+    match pod {
+        Value::None => writeln!(output, "{}None", indent),
+        Value::Bool(value) => writeln!(output, "{}Bool {}", indent, value),
+        Value::Id(value) => writeln!(output, "{}Id {}", indent, value.0),
+        Value::Int(value) => writeln!(output, "{}Int {}", indent, value),
+        Value::Long(value) => writeln!(output, "{}Long {}", indent, value),
+        Value::Float(value) => writeln!(output, "{}Float {}", indent, value),
+        Value::Double(value) => writeln!(output, "{}Double {}", indent, value),
+        Value::String(value) => writeln!(output, "{}String {:?}", indent, value),
+        Value::Bytes(value) => writeln!(output, "{}Bytes {:02x?}", indent, value),
+        Value::Rectangle(value) => writeln!(output, "{}{:?}", indent, value),
+        Value::Fraction(value) => writeln!(output, "{}{:?}", indent, value),
+        Value::Fd(value) => writeln!(output, "{}Fd {}", indent, value.0),
+        Value::ValueArray(value) => writeln!(output, "{}Array {:?}", indent, value),
+        Value::Choice(value) => writeln!(output, "{}Choice {:?}", indent, value),
+        Value::Pointer(type_, pointer) => {
+            writeln!(output, "{}Pointer type {} {:?}", indent, type_, pointer)
+        }
+        Value::Struct(fields) => {
+            writeln!(output, "{}Struct", indent)?;
+            for field in fields {
+                write_pod(output, field, depth + 1)?;
+            }
+            Ok(())
+        }
+        Value::Object(object) => write_object(output, object, depth),
+    }
+}
+
+fn write_object(output: &mut dyn io::Write, object: &Object, depth: usize) -> io::Result<()> {
+    let indent = "  ".repeat(depth);
+    writeln!(
+        output,
+        "{}Object type 0x{:x} id {}",
+        indent, object.type_, object.id
+    )?;
+    for property in &object.properties {
+        writeln!(
+            output,
+            "{}  Prop key {} flags 0x{:x}",
+            indent,
+            property.key,
+            property.flags.bits()
+        )?;
+        write_pod(output, &property.value, depth + 2)?;
+    }
+    Ok(())
+}
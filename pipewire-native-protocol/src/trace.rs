@@ -0,0 +1,20 @@
+// Helpers for the tracing feature
+use spa::{deserialize::PodDeserializer, value::Value};
+
+// The name of a message or event type without the crate path, e.g. "core_proxy::Sync"
+pub(crate) fn type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.strip_prefix("pipewire_native_protocol::")
+        .unwrap_or(name)
+}
+
+// Log the full content of a message at trace level, decoding it is skipped when it would not be logged
+pub(crate) fn dump_pod(bytes: &[u8]) {
+    if !tracing::enabled!(tracing::Level::TRACE) {
+        return;
+    }
+    match PodDeserializer::deserialize_from::<Value>(bytes) {
+        Ok((_, pod)) => tracing::trace!(?pod),
+        Err(_) => tracing::trace!(?bytes, "Message is not a valid pod"),
+    }
+}
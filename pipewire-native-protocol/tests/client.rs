@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+
+use pipewire_native_protocol::{
+    client::{self, ClientEvent, ClientProxy, UpdatePermissions},
+    mock::{global, next_global, MockServer},
+    permissions::Permissions,
+    proxy::Proxy,
+    registry::RegistryProxy,
+};
+
+async fn bind_client(registry: &mut RegistryProxy) -> ClientProxy {
+    let global = next_global(registry).await;
+    registry.bind(&global).await.unwrap()
+}
+
+#[tokio::test]
+async fn bound_client_receives_its_events() {
+    let client = global(
+        40,
+        "PipeWire:Interface:Client",
+        &[("application.name", "player")],
+    );
+    let (server, mut core, mut own_client) = MockServer::connect(vec![client]).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let mut client = bind_client(&mut registry).await;
+    assert_ne!(client.id(), ClientProxy::CLIENT_ID);
+    core.roundtrip().await.unwrap();
+    assert_eq!(client.global_id().await, Some(40));
+
+    server
+        .send_event(
+            client.id(),
+            client::Info {
+                id: 40,
+                change_mask: 1,
+                props: HashMap::from([("application.process.id".to_string(), "42".to_string())]),
+            },
+        )
+        .await
+        .unwrap();
+    match client.recv().await {
+        Some(ClientEvent::Info(info)) => assert_eq!(info.props["application.process.id"], "42"),
+        event => panic!("Expected info, got {:?}", event),
+    }
+    assert!(own_client.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn methods_are_sent_to_the_bound_client() {
+    let client = global(
+        40,
+        "PipeWire:Interface:Client",
+        &[("application.name", "player")],
+    );
+    let (mut server, mut core, _own_client) = MockServer::connect(vec![client]).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let client = bind_client(&mut registry).await;
+
+    client.error(7, -13, "Access denied").await.unwrap();
+    let error: client::Error = server.wait_for_method(client.id()).await;
+    assert_eq!(
+        (error.id, error.res, error.error.as_str()),
+        (7, -13, "Access denied")
+    );
+
+    client
+        .update_permissions(&[(50, Permissions::R)])
+        .await
+        .unwrap();
+    let update: UpdatePermissions = server.wait_for_method(client.id()).await;
+    assert_eq!(update.permissions, vec![(50, Permissions::R)]);
+}
@@ -0,0 +1,26 @@
+use std::io::Cursor;
+
+use pipewire_native_protocol::client_node::{ClientNodeEvent, UseBuffers};
+use spa::{
+    opcode::{DeserializeFromOpCode, MessageOpCode},
+    serialize::PodSerializer,
+    value::Value,
+};
+
+#[test]
+fn truncated_use_buffers_is_an_error() {
+    // Announces a buffer, but the struct ends after the count
+    let truncated = Value::Struct(vec![
+        Value::Int(0),
+        Value::Int(0),
+        Value::Int(0),
+        Value::Int(0),
+        Value::Int(1),
+    ]);
+    let bytes = PodSerializer::serialize(Cursor::new(Vec::new()), &truncated)
+        .unwrap()
+        .0
+        .into_inner();
+    let result = ClientNodeEvent::deserialize_from_opcode(UseBuffers::OP_CODE, &bytes);
+    assert!(result.is_err());
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use pipewire_native_protocol::{
+    core_proxy::{CreateObject, CORE_ID},
+    link::LinkProxy,
+    mock::{global, MockServer},
+    proxy::{BindableProxy, Proxy},
+};
+
+#[tokio::test]
+async fn create_object_returns_the_new_id() {
+    let (mut server, mut core, _client) = MockServer::connect(vec![global(
+        5,
+        "PipeWire:Interface:Factory",
+        &[("factory.name", "link-factory")],
+    )])
+    .await
+    .unwrap();
+
+    let props = HashMap::from([("object.linger".to_string(), "true".to_string())]);
+    let link: LinkProxy = core.create_object("link-factory", props).await.unwrap();
+    let create: CreateObject = server.wait_for_method(CORE_ID).await;
+    assert_eq!(create.new_id, link.id());
+    assert_eq!(create.factory_name, "link-factory");
+    assert_eq!(
+        (create.type_.as_str(), create.version),
+        (LinkProxy::TYPE, LinkProxy::VERSION)
+    );
+    assert_eq!(create.props["object.linger"], "true");
+
+    // Every object gets an id of its own
+    let second: LinkProxy = core
+        .create_object("link-factory", HashMap::new())
+        .await
+        .unwrap();
+    let create: CreateObject = server.wait_for_method(CORE_ID).await;
+    assert_eq!(create.new_id, second.id());
+    assert_ne!(second.id(), link.id());
+}
+
+#[tokio::test]
+async fn failed_create_object_only_fails_itself() {
+    let (_server, mut core, _client) = MockServer::connect(vec![global(
+        5,
+        "PipeWire:Interface:Factory",
+        &[("factory.name", "link-factory")],
+    )])
+    .await
+    .unwrap();
+
+    let result = core
+        .create_object::<LinkProxy>("missing-factory", HashMap::new())
+        .await;
+    assert!(result.is_err());
+    core.roundtrip().await.unwrap();
+    let link: LinkProxy = core
+        .create_object("link-factory", HashMap::new())
+        .await
+        .unwrap();
+    assert!(link.id() > 0);
+}
@@ -0,0 +1,64 @@
+use pipewire_native_protocol::{
+    core_proxy::{BoundId, Destroy, RemoveId, CORE_ID},
+    mock::{global, next_global, MockServer},
+    node::NodeProxy,
+    proxy::Proxy,
+    registry::Global,
+};
+
+fn node() -> Global {
+    global(30, "PipeWire:Interface:Node", &[])
+}
+
+#[tokio::test]
+async fn destroy_sends_destroy() {
+    let (mut server, mut core, _client) = MockServer::connect(vec![node()]).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let node: NodeProxy = registry.bind(&global).await.unwrap();
+    let id = node.id();
+
+    node.destroy().await.unwrap();
+    let destroy: Destroy = server.wait_for_method(CORE_ID).await;
+    assert_eq!(destroy.id, id);
+}
+
+#[tokio::test]
+async fn ids_are_reused_after_remove_id() {
+    let (server, mut core, _client) = MockServer::connect(vec![node()]).await.unwrap();
+    server.acknowledge_destroy(false).await;
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let destroyed: NodeProxy = registry.bind(&global).await.unwrap();
+    let id = destroyed.id();
+    destroyed.destroy().await.unwrap();
+    core.roundtrip().await.unwrap();
+
+    // The server may still send events for the id until it is removed
+    let node: NodeProxy = registry.bind(&global).await.unwrap();
+    assert_ne!(node.id(), id);
+
+    server.send_event(CORE_ID, RemoveId { id }).await.unwrap();
+    core.roundtrip().await.unwrap();
+    let node: NodeProxy = registry.bind(&global).await.unwrap();
+    assert_eq!(node.id(), id);
+}
+
+#[tokio::test]
+async fn global_id_is_the_one_of_bound_id() {
+    let (server, mut core, _client) = MockServer::connect(vec![node()]).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let node: NodeProxy = registry.bind(&global).await.unwrap();
+    core.roundtrip().await.unwrap();
+    assert_eq!(node.global_id().await, Some(30));
+
+    // It follows the server, not the global the proxy was bound from
+    let bound_id = BoundId {
+        id: node.id(),
+        global_id: 31,
+    };
+    server.send_event(CORE_ID, bound_id).await.unwrap();
+    core.roundtrip().await.unwrap();
+    assert_eq!(node.global_id().await, Some(31));
+}
@@ -0,0 +1,192 @@
+use pipewire_native_protocol::{
+    device::{DeviceProxy, Profile, Route, SetParam},
+    mock::{global, next_global, MockServer},
+    param,
+    proxy::Proxy,
+};
+use spa::value::{Id, Object, Property, PropertyFlags, Value, ValueArray};
+
+fn object(type_: u32, id: Id, properties: Vec<(u32, Value)>) -> Value {
+    Value::Object(Object {
+        type_,
+        id: id.0,
+        properties: properties
+            .into_iter()
+            .map(|(key, value)| Property {
+                key,
+                flags: PropertyFlags::empty(),
+                value,
+            })
+            .collect(),
+    })
+}
+
+async fn bind_device() -> (MockServer, DeviceProxy) {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(50, "PipeWire:Interface:Device", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let device = registry.bind(&global).await.unwrap();
+    (server, device)
+}
+
+#[tokio::test]
+async fn profiles_are_parsed_from_their_params() {
+    let (server, mut device) = bind_device().await;
+    // The keys are the ones of spa_param_profile
+    let off = object(
+        param::OBJECT_PARAM_PROFILE,
+        param::PARAM_ENUM_PROFILE,
+        vec![(1, Value::Int(0)), (2, Value::String("off".to_string()))],
+    );
+    let stereo = vec![
+        (1, Value::Int(1)),
+        (
+            Profile::KEY_NAME,
+            Value::String("output:analog-stereo".to_string()),
+        ),
+        (
+            Profile::KEY_DESCRIPTION,
+            Value::String("Analog Stereo Output".to_string()),
+        ),
+        (4, Value::Int(6500)),
+        (5, Value::Id(param::AVAILABILITY_YES)),
+    ];
+    let enum_stereo = object(
+        param::OBJECT_PARAM_PROFILE,
+        param::PARAM_ENUM_PROFILE,
+        stereo.clone(),
+    );
+    for param in [off, Value::Int(1), enum_stereo] {
+        server.add_param(50, param::PARAM_ENUM_PROFILE, param).await;
+    }
+    let active = object(param::OBJECT_PARAM_PROFILE, param::PARAM_PROFILE, stereo);
+    server.add_param(50, param::PARAM_PROFILE, active).await;
+
+    let stereo = Profile {
+        index: 1,
+        name: "output:analog-stereo".to_string(),
+        description: Some("Analog Stereo Output".to_string()),
+        priority: 6500,
+        available: param::AVAILABILITY_YES,
+    };
+    // Params that are not profiles are skipped, the availability is unknown when not given
+    let profiles = device.enum_profiles().await.unwrap();
+    assert_eq!(
+        profiles,
+        vec![
+            Profile {
+                index: 0,
+                name: "off".to_string(),
+                description: None,
+                priority: 0,
+                available: param::AVAILABILITY_UNKNOWN,
+            },
+            stereo.clone(),
+        ]
+    );
+    assert_eq!(device.profile().await.unwrap(), Some(stereo));
+}
+
+#[tokio::test]
+async fn routes_are_parsed_from_their_params() {
+    let (server, mut device) = bind_device().await;
+    // The keys are the ones of spa_param_route
+    let properties = vec![
+        (1, Value::Int(2)),
+        (2, Value::Id(Id(1))),
+        (
+            Route::KEY_NAME,
+            Value::String("analog-output-headphones".to_string()),
+        ),
+        (6, Value::Int(9900)),
+        (7, Value::Id(param::AVAILABILITY_NO)),
+        (
+            Route::KEY_PROFILES,
+            Value::ValueArray(ValueArray::Int(vec![1, 3])),
+        ),
+        (
+            Route::KEY_DEVICES,
+            Value::ValueArray(ValueArray::Int(vec![4])),
+        ),
+    ];
+    let enum_route = object(
+        param::OBJECT_PARAM_ROUTE,
+        param::PARAM_ENUM_ROUTE,
+        properties.clone(),
+    );
+    server
+        .add_param(50, param::PARAM_ENUM_ROUTE, enum_route)
+        .await;
+    let mut active = properties;
+    active.push((3, Value::Int(4)));
+    active.push((12, Value::Int(1)));
+    let active = object(param::OBJECT_PARAM_ROUTE, param::PARAM_ROUTE, active);
+    server.add_param(50, param::PARAM_ROUTE, active).await;
+
+    let headphones = Route {
+        index: 2,
+        direction: Id(1),
+        device: None,
+        name: "analog-output-headphones".to_string(),
+        description: None,
+        priority: 9900,
+        available: param::AVAILABILITY_NO,
+        profiles: vec![1, 3],
+        devices: vec![4],
+        profile: None,
+    };
+    assert_eq!(
+        device.enum_routes().await.unwrap(),
+        vec![headphones.clone()]
+    );
+    // Only active routes have the device and profile they are used on
+    let active = Route {
+        device: Some(4),
+        profile: Some(1),
+        ..headphones
+    };
+    assert_eq!(device.routes().await.unwrap(), vec![active]);
+}
+
+#[tokio::test]
+async fn set_profile_sends_a_profile_object() {
+    let (mut server, device) = bind_device().await;
+    device.set_profile(1).await.unwrap();
+
+    let set_param: SetParam = server.wait_for_method(device.id()).await;
+    assert_eq!((set_param.id, set_param.flags), (Id(9), 0));
+    // An object of type SPA_TYPE_OBJECT_ParamProfile with the index and save keys
+    assert_eq!(
+        set_param.param,
+        object(
+            0x40007,
+            Id(9),
+            vec![(1, Value::Int(1)), (8, Value::Bool(true)),],
+        )
+    );
+}
+
+#[tokio::test]
+async fn set_route_sends_a_route_object() {
+    let (mut server, device) = bind_device().await;
+    device.set_route(2, 4).await.unwrap();
+
+    let set_param: SetParam = server.wait_for_method(device.id()).await;
+    assert_eq!((set_param.id, set_param.flags), (Id(13), 0));
+    // An object of type SPA_TYPE_OBJECT_ParamRoute with the index, device and save keys
+    assert_eq!(
+        set_param.param,
+        object(
+            0x40009,
+            Id(13),
+            vec![
+                (1, Value::Int(2)),
+                (3, Value::Int(4)),
+                (13, Value::Bool(true)),
+            ],
+        )
+    );
+}
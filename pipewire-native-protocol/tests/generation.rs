@@ -0,0 +1,122 @@
+use std::io;
+
+use pipewire_native_protocol::{
+    footer::{self, FOOTER_CLIENT_GENERATION},
+    mock::{global, next_global, MockServer},
+    node::{NodeEvent, NodeProxy},
+    proxy::Proxy,
+    registry::Bind,
+};
+
+#[tokio::test]
+async fn globals_carry_their_generation() {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(30, "PipeWire:Interface:Node", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    core.roundtrip().await.unwrap();
+    assert_eq!(next_global(&mut registry).await.generation(), 0);
+
+    server
+        .add_global(global(31, "PipeWire:Interface:Node", &[]))
+        .await
+        .unwrap();
+    server
+        .add_global(global(32, "PipeWire:Interface:Node", &[]))
+        .await
+        .unwrap();
+    assert_eq!(next_global(&mut registry).await.generation(), 1);
+    assert_eq!(next_global(&mut registry).await.generation(), 2);
+    assert_eq!(core.generation().await, 2);
+}
+
+#[tokio::test]
+async fn bind_sends_the_generation_of_the_global() {
+    let (mut server, mut core, _client) = MockServer::connect(Vec::new()).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    core.roundtrip().await.unwrap();
+    server
+        .add_global(global(31, "PipeWire:Interface:Node", &[]))
+        .await
+        .unwrap();
+    server
+        .add_global(global(32, "PipeWire:Interface:Node", &[]))
+        .await
+        .unwrap();
+    let first = next_global(&mut registry).await;
+    let _second = next_global(&mut registry).await;
+
+    let _node: NodeProxy = registry.bind(&first).await.unwrap();
+    let bind = loop {
+        let method = server.next_method().await.unwrap();
+        if method.is::<Bind>(registry.id()) {
+            break method;
+        }
+    };
+    let generation = footer::generation(&bind.footers(), FOOTER_CLIENT_GENERATION);
+    assert_eq!(generation, Some(1));
+}
+
+#[tokio::test]
+async fn bind_of_removed_global_fails() {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(30, "PipeWire:Interface:Node", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let old = next_global(&mut registry).await;
+
+    server.remove_global(30).await.unwrap();
+    core.roundtrip().await.unwrap();
+
+    let error = registry.bind::<NodeProxy>(&old).await.err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+}
+
+#[tokio::test]
+async fn bind_of_reused_global_is_refused_by_the_server() {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(30, "PipeWire:Interface:Node", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let old = next_global(&mut registry).await;
+
+    // The id is reused by a new global before the old one was bound. The removal is forgotten
+    // once the newer generation is seen, the generation sent with the bind is what refuses it
+    server.remove_global(30).await.unwrap();
+    server
+        .add_global(global(30, "PipeWire:Interface:Node", &[]))
+        .await
+        .unwrap();
+    core.roundtrip().await.unwrap();
+
+    let mut node: NodeProxy = registry.bind(&old).await.unwrap();
+    match node.recv().await {
+        Some(NodeEvent::Error(error)) => assert_eq!(error.res, -2),
+        event => panic!("Expected an error, got {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn server_refuses_globals_newer_than_seen() {
+    let (server, mut core, _client) = MockServer::connect(Vec::new()).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    core.roundtrip().await.unwrap();
+    server
+        .add_global(global(31, "PipeWire:Interface:Node", &[]))
+        .await
+        .unwrap();
+    let _ = next_global(&mut registry).await;
+
+    // A global the client got from before the id was reused, which it was not told about
+    let mut node: NodeProxy = registry
+        .bind(&global(31, "PipeWire:Interface:Node", &[]))
+        .await
+        .unwrap();
+    match node.recv().await {
+        Some(NodeEvent::Error(error)) => assert_eq!(error.res, -2),
+        event => panic!("Expected an error, got {:?}", event),
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use pipewire_native_protocol::{
+    factory::{self, FactoryProxy},
+    mock::{global, next_global, MockServer},
+    module::{self, ModuleEvent, ModuleProxy},
+    proxy::{BindableProxy, Proxy},
+    registry::RegistryProxy,
+};
+
+fn props(entries: &[(&str, &str)]) -> HashMap<String, String> {
+    entries
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+async fn bind<P: BindableProxy>(registry: &mut RegistryProxy) -> P {
+    let global = next_global(registry).await;
+    registry.bind(&global).await.unwrap()
+}
+
+fn module_info(change_mask: i64, props: HashMap<String, String>) -> module::Info {
+    module::Info {
+        id: 20,
+        name: "libpipewire-module-rt".to_string(),
+        file_name: "/usr/lib/pipewire-0.3/libpipewire-module-rt.so".to_string(),
+        args: Some("{ nice.level = -11 }".to_string()),
+        change_mask,
+        props,
+    }
+}
+
+#[tokio::test]
+async fn module_info_updates_are_merged() {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(20, "PipeWire:Interface:Module", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let mut module: ModuleProxy = bind(&mut registry).await;
+    assert!(module.info().is_none());
+
+    let first = props(&[("module.name", "rt"), ("object.id", "20")]);
+    for update in [
+        module_info(module::Info::CHANGE_MASK_ALL, first.clone()),
+        module_info(0, HashMap::new()),
+    ] {
+        server.send_event(module.id(), update).await.unwrap();
+        assert!(matches!(module.recv().await, Some(ModuleEvent::Info(_))));
+    }
+    // The props of the first info are kept, the mask is the one of the update that changed nothing
+    let info = module.info().unwrap();
+    assert_eq!((info.change_mask, &info.props), (0, &first));
+
+    let second = props(&[("module.name", "rt"), ("object.serial", "55")]);
+    let update = module_info(module::Info::CHANGE_MASK_PROPS, second.clone());
+    server.send_event(module.id(), update).await.unwrap();
+    module.recv().await.unwrap();
+    let info = module.info().unwrap();
+    assert_eq!(
+        (info.change_mask, &info.props),
+        (module::Info::CHANGE_MASK_PROPS, &second)
+    );
+}
+
+#[tokio::test]
+async fn wait_for_info_returns_the_factory_info() {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(7, "PipeWire:Interface:Factory", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let mut factory: FactoryProxy = bind(&mut registry).await;
+
+    let info = factory::Info {
+        id: 7,
+        name: "adapter".to_string(),
+        type_: "PipeWire:Interface:Node".to_string(),
+        version: 3,
+        change_mask: factory::Info::CHANGE_MASK_ALL,
+        props: props(&[("factory.name", "adapter")]),
+    };
+    server.send_event(factory.id(), info.clone()).await.unwrap();
+    assert_eq!(factory.wait_for_info().await.unwrap(), &info);
+}
@@ -0,0 +1,81 @@
+use pipewire_native_protocol::{
+    metadata::{MetadataEvent, MetadataProxy, Property},
+    mock::{global, next_global, MockServer},
+    proxy::Proxy,
+};
+
+fn property(subject: i32, key: Option<&str>, value: Option<&str>) -> Property {
+    Property {
+        subject,
+        key: key.map(str::to_string),
+        type_: value.map(|_| "Spa:String:JSON".to_string()),
+        value: value.map(str::to_string),
+    }
+}
+
+async fn bind_metadata() -> (MockServer, MetadataProxy) {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(40, "PipeWire:Interface:Metadata", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let metadata = registry.bind(&global).await.unwrap();
+    (server, metadata)
+}
+
+#[tokio::test]
+async fn properties_are_set_updated_and_cleared() {
+    let (server, mut metadata) = bind_metadata().await;
+    let sink = Some("default.audio.sink");
+    for property in [
+        property(0, sink, Some(r#"{"name":"speakers"}"#)),
+        property(0, Some("default.audio.source"), Some(r#"{"name":"mic"}"#)),
+        property(31, Some("target.object"), Some("42")),
+    ] {
+        server.send_event(metadata.id(), property).await.unwrap();
+    }
+    metadata.wait_for_properties().await.unwrap();
+    assert_eq!(metadata.properties().len(), 3);
+    assert_eq!(
+        metadata.get(0, "default.audio.sink"),
+        Some((Some("Spa:String:JSON"), r#"{"name":"speakers"}"#))
+    );
+
+    // The events are still delivered after wait_for_properties applied them
+    for _ in 0..3 {
+        assert!(matches!(
+            metadata.recv().await,
+            Some(MetadataEvent::Property(_))
+        ));
+    }
+
+    // A new value replaces the old one
+    let update = property(0, sink, Some(r#"{"name":"headphones"}"#));
+    server.send_event(metadata.id(), update).await.unwrap();
+    match metadata.recv().await {
+        Some(MetadataEvent::Property(property)) => assert_eq!(property.key.as_deref(), sink),
+        event => panic!("Expected a property, got {:?}", event),
+    }
+    assert_eq!(
+        metadata.get(0, "default.audio.sink"),
+        Some((Some("Spa:String:JSON"), r#"{"name":"headphones"}"#))
+    );
+
+    // Without a value the property is removed, without a key all properties of the subject
+    server
+        .send_event(metadata.id(), property(0, sink, None))
+        .await
+        .unwrap();
+    server
+        .send_event(metadata.id(), property(31, None, None))
+        .await
+        .unwrap();
+    metadata.wait_for_properties().await.unwrap();
+    assert_eq!(metadata.get(0, "default.audio.sink"), None);
+    assert_eq!(metadata.get(31, "target.object"), None);
+    assert_eq!(
+        metadata.get(0, "default.audio.source"),
+        Some((Some("Spa:String:JSON"), r#"{"name":"mic"}"#))
+    );
+}
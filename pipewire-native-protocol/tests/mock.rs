@@ -0,0 +1,210 @@
+use std::{collections::HashMap, time::Duration};
+
+use pipewire_native_protocol::{
+    client::{ClientProxy, UpdateProperties},
+    core_proxy::{self, CoreEvent, Hello, Ping, Pong, CORE_ID},
+    error::{DisconnectReason, MessageError},
+    mock::{global, next_global, MockServer},
+    node::{self, NodeEvent, NodeProxy},
+    param::ParamInfos,
+    proxy::Proxy,
+    registry::{Bind, RegistryEvent},
+};
+use spa::{opcode::MessageOpCode, value::Id};
+
+// More than the default capacity of the core channel
+const NODES: i32 = 20;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn handshake_and_sync() {
+    let (mut server, mut core, _client) = MockServer::connect(Vec::new()).await.unwrap();
+    let hello: Hello = server.wait_for_method(CORE_ID).await;
+    assert_eq!(hello.version, 3);
+    let properties: UpdateProperties = server.wait_for_method(ClientProxy::CLIENT_ID).await;
+    assert!(properties.props.contains_key("application.name"));
+
+    core.roundtrip().await.unwrap();
+    let sync: core_proxy::Sync = server.wait_for_method(CORE_ID).await;
+    assert_eq!(sync.id, CORE_ID);
+}
+
+#[tokio::test]
+async fn registry_lists_globals() {
+    let first = global(30, "PipeWire:Interface:Node", &[("node.name", "first")]);
+    let (server, mut core, _client) = MockServer::connect(vec![first]).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let listed = next_global(&mut registry).await;
+    assert_eq!(
+        (listed.id, listed.props["node.name"].as_str()),
+        (30, "first")
+    );
+
+    let second = global(31, "PipeWire:Interface:Node", &[("node.name", "second")]);
+    server.add_global(second).await.unwrap();
+    assert_eq!(next_global(&mut registry).await.id, 31);
+
+    server.remove_global(30).await.unwrap();
+    match registry.recv().await {
+        Some(RegistryEvent::GlobalRemove(remove)) => assert_eq!(remove.id, 30),
+        event => panic!("Expected a global remove, got {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn bound_proxy_receives_events() {
+    let node = global(30, "PipeWire:Interface:Node", &[("node.name", "node")]);
+    let (mut server, mut core, _client) = MockServer::connect(vec![node]).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let mut node: NodeProxy = registry.bind(&global).await.unwrap();
+    let bind: Bind = server.wait_for_method(registry.id()).await;
+    assert_eq!(bind.id, 30);
+    assert_eq!(server.bound_global(bind.new_id).await, Some(30));
+
+    server
+        .send_event(
+            bind.new_id,
+            node::Info {
+                id: 30,
+                max_input_ports: 1,
+                max_output_ports: 1,
+                change_mask: 0,
+                n_input_ports: 0,
+                n_output_ports: 0,
+                state: Id(3),
+                error: None,
+                props: HashMap::new(),
+                param_info: ParamInfos::default(),
+            },
+        )
+        .await
+        .unwrap();
+    match node.recv().await {
+        Some(NodeEvent::Info(info)) => assert_eq!((info.id, info.state), (30, Id(3))),
+        event => panic!("Expected node info, got {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn ping_is_answered() {
+    let (mut server, _core, _client) = MockServer::connect(Vec::new()).await.unwrap();
+    server
+        .send_event(
+            CORE_ID,
+            Ping {
+                id: CORE_ID,
+                seq: 42,
+            },
+        )
+        .await
+        .unwrap();
+    let pong: Pong = server.wait_for_method(CORE_ID).await;
+    assert_eq!((pong.id, pong.seq), (CORE_ID, 42));
+}
+
+#[tokio::test]
+async fn ping_is_answered_while_the_core_is_not_read() {
+    let globals = (0..NODES)
+        .map(|id| global(30 + id, "PipeWire:Interface:Node", &[]))
+        .collect();
+    let (mut server, mut core, _client) = MockServer::connect(globals).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    // Every bind sends a BoundId to the core, more than its channel holds
+    let mut nodes: Vec<NodeProxy> = Vec::new();
+    for _ in 0..NODES {
+        let global = next_global(&mut registry).await;
+        nodes.push(registry.bind(&global).await.unwrap());
+    }
+    registry.roundtrip().await.unwrap();
+
+    server
+        .send_event(
+            CORE_ID,
+            Ping {
+                id: CORE_ID,
+                seq: 7,
+            },
+        )
+        .await
+        .unwrap();
+    let pong: Pong = tokio::time::timeout(TIMEOUT, server.wait_for_method(CORE_ID))
+        .await
+        .expect("The ping was not answered");
+    assert_eq!(pong.seq, 7);
+
+    // The core still gets its events in order once it is read
+    let mut bound = Vec::new();
+    loop {
+        match core.recv().await {
+            Some(CoreEvent::BoundId(bound_id)) => bound.push(bound_id.id),
+            Some(CoreEvent::Ping(ping)) => {
+                assert_eq!(ping.seq, 7);
+                break;
+            }
+            event => panic!("Unexpected core event {:?}", event),
+        }
+    }
+    let ids: Vec<i32> = nodes.iter().map(|node| node.id()).collect();
+    assert_eq!(bound, ids);
+}
+
+// The next message the core reports as skipped, the other core events are passed over
+async fn next_skipped(core: &mut core_proxy::CoreProxy) -> MessageError {
+    loop {
+        match tokio::time::timeout(TIMEOUT, core.recv()).await.unwrap() {
+            Some(CoreEvent::SkippedMessage(error)) => return error,
+            Some(_) => continue,
+            None => panic!("The core was closed"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn skipped_messages_are_reported() {
+    let (server, mut core, _client) = MockServer::connect(Vec::new()).await.unwrap();
+    let registry = core.get_registry().await.unwrap();
+    core.roundtrip().await.unwrap();
+
+    // The registry has no event with the opcode of a ping
+    let ping = Ping { id: 0, seq: 1 };
+    server.send_event(registry.id(), ping).await.unwrap();
+    assert_eq!(
+        next_skipped(&mut core).await,
+        MessageError::Invalid {
+            id: registry.id(),
+            opcode: Ping::OP_CODE
+        }
+    );
+
+    server
+        .send_event(999, Ping { id: 0, seq: 2 })
+        .await
+        .unwrap();
+    assert_eq!(
+        next_skipped(&mut core).await,
+        MessageError::UnknownProxy {
+            id: 999,
+            opcode: Ping::OP_CODE
+        }
+    );
+
+    // The connection goes on after a skipped message
+    core.roundtrip().await.unwrap();
+}
+
+#[tokio::test]
+async fn server_close_disconnects() {
+    let (server, mut core, _client) = MockServer::connect(Vec::new()).await.unwrap();
+    server.close().await.unwrap();
+    loop {
+        match core.recv().await {
+            Some(CoreEvent::Disconnected(reason)) => {
+                assert!(matches!(reason, DisconnectReason::ServerClosed));
+                break;
+            }
+            Some(_) => continue,
+            None => panic!("Core channel closed without a Disconnected event"),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use pipewire_native_protocol::{
+    mock::{global, next_global, MockServer},
+    node::{self, NodeEvent, NodeProxy},
+    param::{self, ParamInfos},
+    port::PortProxy,
+    proxy::Proxy,
+};
+use spa::value::{Id, Value};
+
+fn node_info() -> node::Info {
+    node::Info {
+        id: 30,
+        max_input_ports: 1,
+        max_output_ports: 1,
+        change_mask: 0,
+        n_input_ports: 0,
+        n_output_ports: 0,
+        state: Id(3),
+        error: None,
+        props: HashMap::new(),
+        param_info: ParamInfos::default(),
+    }
+}
+
+#[tokio::test]
+async fn enum_params_keeps_the_other_events() {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(30, "PipeWire:Interface:Node", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let mut node: NodeProxy = registry.bind(&global).await.unwrap();
+    for value in [1, 2] {
+        server
+            .add_param(30, param::PARAM_PROPS, Value::Int(value))
+            .await;
+    }
+    server
+        .add_param(30, param::PARAM_FORMAT, Value::Int(3))
+        .await;
+
+    server.send_event(node.id(), node_info()).await.unwrap();
+    let params = node
+        .enum_params(param::PARAM_PROPS, Value::None)
+        .await
+        .unwrap();
+    let values: Vec<Value> = params.into_iter().map(|param| param.param).collect();
+    assert_eq!(values, vec![Value::Int(1), Value::Int(2)]);
+    assert!(matches!(node.recv().await, Some(NodeEvent::Info(_))));
+}
+
+#[tokio::test]
+async fn ports_enumerate_their_params() {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(31, "PipeWire:Interface:Port", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let mut port: PortProxy = registry.bind(&global).await.unwrap();
+    server
+        .add_param(31, param::PARAM_ENUM_FORMAT, Value::Int(4))
+        .await;
+
+    let params = port
+        .enum_params(param::PARAM_ENUM_FORMAT, Value::None)
+        .await
+        .unwrap();
+    assert_eq!(params.len(), 1);
+    assert_eq!(
+        (params[0].id, &params[0].param),
+        (param::PARAM_ENUM_FORMAT, &Value::Int(4))
+    );
+}
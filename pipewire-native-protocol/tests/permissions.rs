@@ -0,0 +1,74 @@
+use pipewire_native_protocol::{
+    client::{ClientProxy, GetPermissions, UpdatePermissions},
+    mock::MockServer,
+    permissions::{Permissions, ID_ANY},
+};
+use spa::{deserialize::PodDeserializer, value::Value};
+
+#[tokio::test]
+async fn updated_permissions_are_listed() {
+    let (_server, _core, mut client) = MockServer::connect(Vec::new()).await.unwrap();
+    client
+        .update_permissions(&[(ID_ANY, Permissions::R), (32, Permissions::RWX)])
+        .await
+        .unwrap();
+    client
+        .update_permissions(&[(32, Permissions::ALL)])
+        .await
+        .unwrap();
+
+    let permissions = client.get_permissions(0, 0).await.unwrap();
+    assert_eq!(
+        permissions,
+        vec![(ID_ANY, Permissions::R), (32, Permissions::ALL)]
+    );
+    let permissions = client.get_permissions(1, 1).await.unwrap();
+    assert_eq!(permissions, vec![(32, Permissions::ALL)]);
+}
+
+#[tokio::test]
+async fn get_permissions_sends_the_range() {
+    let (mut server, _core, mut client) = MockServer::connect(Vec::new()).await.unwrap();
+    client.get_permissions(1, 2).await.unwrap();
+    let get: GetPermissions = server.wait_for_method(ClientProxy::CLIENT_ID).await;
+    assert_eq!((get.index, get.num), (1, 2));
+
+    // A num of 0 asks for all permissions from index on
+    client.get_permissions(3, 0).await.unwrap();
+    let get: GetPermissions = server.wait_for_method(ClientProxy::CLIENT_ID).await;
+    assert_eq!((get.index, get.num), (3, i32::MAX));
+}
+
+#[tokio::test]
+async fn update_permissions_is_sent_flat() {
+    let (mut server, _core, client) = MockServer::connect(Vec::new()).await.unwrap();
+    client
+        .update_permissions(&[(ID_ANY, Permissions::R), (32, Permissions::RWX)])
+        .await
+        .unwrap();
+
+    let method = loop {
+        let method = server.next_method().await.unwrap();
+        if method.is::<UpdatePermissions>(ClientProxy::CLIENT_ID) {
+            break method;
+        }
+    };
+    let (_, value) = PodDeserializer::deserialize_any_from(&method.payload).unwrap();
+    assert_eq!(
+        value,
+        Value::Struct(vec![
+            Value::Int(2),
+            Value::Int(-1),
+            Value::Int(0o400),
+            Value::Int(32),
+            Value::Int(0o700),
+        ])
+    );
+}
+
+#[test]
+fn unknown_permission_bits_are_kept() {
+    let permissions = Permissions::from_bits_retain(0o400 | 0o1000);
+    assert!(permissions.contains(Permissions::R));
+    assert_eq!(permissions.bits(), 0o1400);
+}
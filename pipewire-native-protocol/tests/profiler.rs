@@ -0,0 +1,125 @@
+use pipewire_native_protocol::{
+    mock::{global, next_global, MockServer},
+    profiler::{Profile, ProfilerEvent, ProfilerProxy, Report, OBJECT_PROFILER},
+    proxy::Proxy,
+};
+use spa::value::{Fraction, Object, Property, PropertyFlags, Value};
+
+fn property(key: u32, fields: Vec<Value>) -> Property {
+    Property {
+        key,
+        flags: PropertyFlags::empty(),
+        value: Value::Struct(fields),
+    }
+}
+
+fn block(id: i32, name: &str, signal: i64) -> Vec<Value> {
+    vec![
+        Value::Int(id),
+        Value::String(name.to_string()),
+        Value::Long(signal - 1000),
+        Value::Long(signal),
+        Value::Long(signal + 20),
+        Value::Long(signal + 70),
+        Value::Int(3),
+        Value::Fraction(Fraction {
+            num: 1024,
+            denom: 48000,
+        }),
+        Value::Int(2),
+    ]
+}
+
+// A cycle as sent by the server, the clock without the fields added in later versions
+fn profiler_object() -> Value {
+    Value::Object(Object {
+        type_: OBJECT_PROFILER,
+        id: 0,
+        properties: vec![
+            property(
+                Report::KEY_INFO,
+                vec![
+                    Value::Long(12),
+                    Value::Float(0.25),
+                    Value::Float(0.5),
+                    Value::Float(0.75),
+                    Value::Int(1),
+                ],
+            ),
+            property(
+                Report::KEY_CLOCK,
+                vec![
+                    Value::Int(0),
+                    Value::Int(30),
+                    Value::String("clock.system.monotonic".to_string()),
+                    Value::Long(5000),
+                    Value::Fraction(Fraction {
+                        num: 1,
+                        denom: 48000,
+                    }),
+                    Value::Long(9216),
+                    Value::Long(1024),
+                    Value::Long(0),
+                    Value::Double(1.0),
+                    Value::Long(26333),
+                ],
+            ),
+            property(Report::KEY_DRIVER_BLOCK, block(30, "alsa_output", 5000)),
+            property(Report::KEY_FOLLOWER_BLOCK, block(41, "player", 5100)),
+            property(Report::KEY_FOLLOWER_BLOCK, block(42, "recorder", 5200)),
+        ],
+    })
+}
+
+#[test]
+fn report_is_decoded() {
+    let profile = Profile {
+        object: Value::Struct(vec![profiler_object(), Value::Int(0)]),
+    };
+    let reports = profile.reports();
+    assert_eq!(reports.len(), 1);
+    let report = &reports[0];
+
+    let info = report.info.as_ref().unwrap();
+    assert_eq!(
+        (info.counter, info.cpu_load, info.xrun_count),
+        (12, [0.25, 0.5, 0.75], 1)
+    );
+    let clock = report.clock.as_ref().unwrap();
+    assert_eq!(
+        (clock.id, clock.duration, clock.rate.denom),
+        (30, 1024, 48000)
+    );
+    assert_eq!((clock.transport_state, clock.xrun_duration), (None, None));
+
+    let driver = report.driver.as_ref().unwrap();
+    assert_eq!((driver.id, driver.name.as_str()), (30, "alsa_output"));
+    assert_eq!((driver.waiting(), driver.busy()), (20, 50));
+    assert_eq!(driver.xrun_count, Some(2));
+    let followers: Vec<_> = report.followers.iter().map(|block| block.id).collect();
+    assert_eq!(followers, vec![41, 42]);
+}
+
+#[tokio::test]
+async fn profile_event_is_received() {
+    let (server, mut core, _client) =
+        MockServer::connect(vec![global(5, "PipeWire:Interface:Profiler", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let mut profiler: ProfilerProxy = registry.bind(&global).await.unwrap();
+
+    let object = Value::Struct(vec![profiler_object()]);
+    server
+        .send_event(profiler.id(), Profile { object })
+        .await
+        .unwrap();
+    match profiler.recv().await {
+        Some(ProfilerEvent::Profile(profile)) => {
+            let reports = profile.reports();
+            assert_eq!(reports[0].followers.len(), 2);
+        }
+        event => panic!("Expected a profile, got {:?}", event),
+    }
+}
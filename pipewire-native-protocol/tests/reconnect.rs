@@ -0,0 +1,224 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use pipewire_native_protocol::{
+    builder::{ChannelCapacities, ConnectionBuilder},
+    client::ClientProxy,
+    core_proxy::{CoreProxy, CreateObject, GetRegistry},
+    error::DisconnectReason,
+    mock::{global, next_global, MockServer},
+    node::{NodeEvent, NodeProxy},
+    param::Param,
+    proxy::Proxy,
+    reconnect::ReconnectPolicy,
+    registry::{Bind, Global, RegistryEvent, RegistryProxy},
+};
+use spa::value::{Id, Value};
+use tokio::net::UnixListener;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// A socket of its own for every test, the tests run in parallel
+fn listen(test: &str) -> (UnixListener, PathBuf) {
+    let path = std::env::temp_dir().join(format!("pipewire-mock-{}-{}", std::process::id(), test));
+    let _ = std::fs::remove_file(&path);
+    (UnixListener::bind(&path).unwrap(), path)
+}
+
+fn client(path: &Path, max_attempts: Option<u32>) -> ConnectionBuilder {
+    let policy = ReconnectPolicy {
+        initial_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(50),
+        max_attempts,
+    };
+    ConnectionBuilder::new()
+        .remote(path.to_str().unwrap())
+        .reconnect(policy)
+}
+
+async fn connect(
+    listener: &UnixListener,
+    globals: Vec<Global>,
+    client: ConnectionBuilder,
+) -> (MockServer, CoreProxy, ClientProxy) {
+    let (server, connection) =
+        tokio::join!(MockServer::accept(listener, globals), client.connect());
+    let (core, client) = connection.unwrap();
+    (server.unwrap(), core, client)
+}
+
+fn speaker(id: i32) -> Global {
+    global(id, "PipeWire:Interface:Node", &[("node.name", "speaker")])
+}
+
+async fn bind_node(registry: &mut RegistryProxy) -> NodeProxy {
+    let global = next_global(registry).await;
+    registry.bind(&global).await.unwrap()
+}
+
+async fn next_event(node: &mut NodeProxy) -> NodeEvent {
+    tokio::time::timeout(TIMEOUT, node.recv())
+        .await
+        .expect("No event for the node")
+        .expect("The node channel closed")
+}
+
+#[tokio::test]
+async fn bound_proxy_is_restored() {
+    let (listener, path) = listen("restored");
+    let (server, mut core, _client) =
+        connect(&listener, vec![speaker(30)], client(&path, None)).await;
+    let mut registry = core.get_registry().await.unwrap();
+    let mut node = bind_node(&mut registry).await;
+
+    server.close().await.unwrap();
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::ConnectionLost(_)
+    ));
+    // The daemon restarted and the node has a new global id
+    let server = MockServer::accept(&listener, vec![speaker(31)])
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::Reconnected
+    ));
+    core.roundtrip().await.unwrap();
+    assert_eq!(server.bound_global(node.id()).await, Some(31));
+}
+
+#[tokio::test]
+async fn unread_proxy_does_not_hold_up_the_others() {
+    let (listener, path) = listen("unread");
+    let capacities = ChannelCapacities {
+        node: 1,
+        ..Default::default()
+    };
+    let microphone = global(31, "PipeWire:Interface:Node", &[("node.name", "mic")]);
+    let globals = vec![speaker(30), microphone.clone()];
+    let client = client(&path, None).channel_capacities(capacities);
+    let (server, mut core, _client) = connect(&listener, globals.clone(), client).await;
+    let mut registry = core.get_registry().await.unwrap();
+    let unread = bind_node(&mut registry).await;
+    let mut node = bind_node(&mut registry).await;
+    let param = Param {
+        seq: 0,
+        id: Id(2),
+        index: 0,
+        next: 1,
+        param: Value::None,
+    };
+    server.send_event(unread.id(), param).await.unwrap();
+    core.roundtrip().await.unwrap();
+
+    server.close().await.unwrap();
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::ConnectionLost(_)
+    ));
+    let _server = MockServer::accept(&listener, globals).await.unwrap();
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::Reconnected
+    ));
+}
+
+#[tokio::test]
+async fn proxy_of_a_lost_global_is_disconnected() {
+    let (listener, path) = listen("lost");
+    let (server, mut core, _client) =
+        connect(&listener, vec![speaker(30)], client(&path, None)).await;
+    let mut registry = core.get_registry().await.unwrap();
+    let mut node = bind_node(&mut registry).await;
+
+    server.close().await.unwrap();
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::ConnectionLost(_)
+    ));
+    let _server = MockServer::accept(&listener, Vec::new()).await.unwrap();
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::Disconnected(DisconnectReason::ServerClosed)
+    ));
+}
+
+#[tokio::test]
+async fn reconnecting_gives_up_after_max_attempts() {
+    let (listener, path) = listen("gives-up");
+    let (server, mut core, _client) =
+        connect(&listener, vec![speaker(30)], client(&path, Some(2))).await;
+    let mut registry = core.get_registry().await.unwrap();
+    let mut node = bind_node(&mut registry).await;
+
+    drop(listener);
+    std::fs::remove_file(&path).unwrap();
+    server.close().await.unwrap();
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::ConnectionLost(_)
+    ));
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::Disconnected(DisconnectReason::Error(_))
+    ));
+}
+
+#[tokio::test]
+async fn ids_that_are_not_restored_are_reserved() {
+    let (listener, path) = listen("reserved");
+    let globals = vec![
+        global(40, "PipeWire:Interface:Node", &[]),
+        global(41, "PipeWire:Interface:Node", &[]),
+        speaker(30),
+    ];
+    let (server, mut core, _client) = connect(&listener, globals, client(&path, None)).await;
+    let mut registry = core.get_registry().await.unwrap();
+    let dropped = bind_node(&mut registry).await;
+    let reused = bind_node(&mut registry).await;
+    let mut node = bind_node(&mut registry).await;
+    let (dropped_id, reused_id, node_id) = (dropped.id(), reused.id(), node.id());
+    drop(dropped);
+    drop(reused);
+    core.roundtrip().await.unwrap();
+
+    server.close().await.unwrap();
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::ConnectionLost(_)
+    ));
+    let mut server = MockServer::accept(&listener, vec![speaker(31)])
+        .await
+        .unwrap();
+    // The id of a dropped proxy is taken by a CreateObject for a factory that does not exist, so
+    // the server accepts the ids after it. The last id that was freed is used for the registry
+    // that finds the globals again
+    let create: CreateObject = server.wait_for_method(0).await;
+    assert_eq!(
+        (create.factory_name.as_str(), create.new_id),
+        ("", dropped_id)
+    );
+    let get_registry: GetRegistry = server.wait_for_method(0).await;
+    assert_eq!(get_registry.new_id, reused_id);
+    let create: CreateObject = server.wait_for_method(0).await;
+    assert_eq!(create.new_id, node_id);
+    assert!(matches!(
+        next_event(&mut node).await,
+        NodeEvent::Reconnected
+    ));
+
+    // The reserved id is used again for the next proxy
+    let node_global = loop {
+        match registry.recv().await {
+            Some(RegistryEvent::Global(global)) => break global,
+            Some(_) => (),
+            None => panic!("The registry channel closed"),
+        }
+    };
+    let new_node: NodeProxy = registry.bind(&node_global).await.unwrap();
+    assert!([dropped_id, reused_id].contains(&new_node.id()));
+    let _: Bind = server.wait_for_method(registry.id()).await;
+}
@@ -0,0 +1,116 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use pipewire_native_protocol::{
+    core_proxy::CoreProxy,
+    mock::{global, MockServer},
+    record::{Direction, Recording},
+    registry::RegistryEvent,
+    replay::ReplayServer,
+    PipewireConnection,
+};
+
+// A recording destination the test can read back
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The client code under test, lists the globals
+async fn list_globals(core: &mut CoreProxy) -> Vec<i32> {
+    let mut registry = core.get_registry().await.unwrap();
+    core.roundtrip().await.unwrap();
+    let mut ids = Vec::new();
+    while let Ok(RegistryEvent::Global(global)) = registry.try_recv() {
+        ids.push(global.id);
+    }
+    ids
+}
+
+async fn record_session() -> Recording {
+    let buffer = SharedBuffer::default();
+    let (_server, stream) = MockServer::new(vec![
+        global(
+            40,
+            "PipeWire:Interface:Device",
+            &[("device.name", "device-40")],
+        ),
+        global(
+            41,
+            "PipeWire:Interface:Device",
+            &[("device.name", "device-41")],
+        ),
+    ])
+    .unwrap();
+    let (mut core, _client) = PipewireConnection::builder()
+        .record(buffer.clone())
+        .connect_stream(stream)
+        .await
+        .unwrap();
+    assert_eq!(list_globals(&mut core).await, vec![40, 41]);
+    let bytes = buffer.0.lock().unwrap().clone();
+    Recording::read_from(bytes.as_slice()).unwrap()
+}
+
+#[tokio::test]
+async fn recording_has_both_directions() {
+    let recording = record_session().await;
+    let directions: Vec<_> = recording
+        .messages
+        .iter()
+        .map(|message| (message.direction, message.id, message.opcode))
+        .collect();
+    // Hello, UpdateProperties, GetRegistry and Sync, then two globals and the done
+    assert_eq!(
+        directions,
+        vec![
+            (Direction::Send, 0, 1),
+            (Direction::Send, 1, 2),
+            (Direction::Send, 0, 5),
+            (Direction::Send, 0, 2),
+            (Direction::Receive, 2, 0),
+            (Direction::Receive, 2, 0),
+            (Direction::Receive, 0, 1),
+        ]
+    );
+    assert!(recording
+        .messages
+        .windows(2)
+        .all(|pair| pair[0].time <= pair[1].time));
+}
+
+#[tokio::test]
+async fn replay_reproduces_the_session() {
+    let recording = record_session().await;
+    let (server, mut core, _client) = ReplayServer::connect(recording).await.unwrap();
+    assert_eq!(list_globals(&mut core).await, vec![40, 41]);
+    server.finished().await.unwrap();
+}
+
+#[tokio::test]
+async fn replay_detects_other_methods() {
+    let recording = record_session().await;
+    let (server, mut core, _client) = ReplayServer::connect(recording).await.unwrap();
+    // The recorded session got the registry before syncing
+    let pending_sync = core.sync(0).await.unwrap();
+    drop(pending_sync);
+    let error = server.finished().await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn invalid_recording_is_rejected() {
+    let error = Recording::from_bytes(b"not a recording").unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use pipewire_native_protocol::{
+    builder::{ChannelCapacities, ConnectionBuilder},
+    core_proxy::{Done, ErrorEvent, CORE_ID},
+    mock::{global, next_global, MockServer},
+    node::NodeProxy,
+    param,
+    proxy::Proxy,
+    registry::RegistryEvent,
+};
+use spa::value::Value;
+use tokio::net::UnixListener;
+
+// More than the default capacity of the core and client channels
+const ROUNDTRIPS: usize = 20;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn roundtrips_do_not_fill_the_channels() {
+    let (_server, mut core, mut client) = MockServer::connect(Vec::new()).await.unwrap();
+    for _ in 0..ROUNDTRIPS {
+        tokio::time::timeout(TIMEOUT, client.roundtrip())
+            .await
+            .expect("The client roundtrip hung")
+            .unwrap();
+        tokio::time::timeout(TIMEOUT, core.roundtrip())
+            .await
+            .expect("The core roundtrip hung")
+            .unwrap();
+    }
+    // The done events only resolved the syncs
+    assert!(client.try_recv().is_err());
+    assert!(core.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn empty_channels_are_given_room_for_one_event() {
+    let path = std::env::temp_dir().join(format!("pipewire-mock-{}-empty", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let capacities = ChannelCapacities {
+        core: 0,
+        client: 0,
+        registry: 0,
+        ..Default::default()
+    };
+    let client = ConnectionBuilder::new()
+        .remote(path.to_str().unwrap())
+        .channel_capacities(capacities);
+    let globals = vec![global(30, "PipeWire:Interface:Node", &[])];
+    let (server, connection) =
+        tokio::join!(MockServer::accept(&listener, globals), client.connect());
+    let _server = server.unwrap();
+    let (mut core, _client) = connection.unwrap();
+
+    let mut registry = core.get_registry().await.unwrap();
+    assert_eq!(next_global(&mut registry).await.id, 30);
+    core.roundtrip().await.unwrap();
+}
+
+#[tokio::test]
+async fn unread_registry_does_not_hold_up_the_roundtrip() {
+    // More globals than the registry channel holds by default
+    let globals = (0..150)
+        .map(|id| global(100 + id, "PipeWire:Interface:Node", &[]))
+        .collect();
+    let (_server, mut core, _client) = MockServer::connect(globals).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    tokio::time::timeout(TIMEOUT, core.roundtrip())
+        .await
+        .expect("The roundtrip hung")
+        .unwrap();
+
+    // The globals that did not fit wait behind the channel, in order
+    let mut ids = Vec::new();
+    for _ in 0..150 {
+        ids.push(next_global(&mut registry).await.id);
+    }
+    assert_eq!(ids, (100..250).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn errors_and_dones_keep_their_order_behind_a_full_channel() {
+    let globals = (0..150)
+        .map(|id| global(100 + id, "PipeWire:Interface:Node", &[]))
+        .collect();
+    let (server, mut core, _client) = MockServer::connect(globals).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    core.roundtrip().await.unwrap();
+
+    // A failed call is reported as an error followed by the done of the sync after it
+    let error = ErrorEvent {
+        id: registry.id(),
+        seq: 1,
+        res: -22,
+        message: "Invalid argument".to_string(),
+    };
+    server.send_event(CORE_ID, error).await.unwrap();
+    let done = Done {
+        id: registry.id(),
+        seq: 2,
+    };
+    server.send_event(CORE_ID, done).await.unwrap();
+    core.roundtrip().await.unwrap();
+
+    for _ in 0..150 {
+        next_global(&mut registry).await;
+    }
+    match registry.recv().await {
+        Some(RegistryEvent::Error(error)) => assert_eq!((error.seq, error.res), (1, -22)),
+        event => panic!("Expected the error, got {:?}", event),
+    }
+    match registry.recv().await {
+        Some(RegistryEvent::Done(done)) => assert_eq!(done.seq, 2),
+        event => panic!("Expected the done, got {:?}", event),
+    }
+}
+
+#[tokio::test]
+async fn unread_proxies_do_not_hold_up_checked_calls() {
+    let globals = (0..10)
+        .map(|id| global(30 + id, "PipeWire:Interface:Node", &[]))
+        .collect();
+    let (_server, mut core, _client) = MockServer::connect(globals).await.unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let mut nodes: Vec<NodeProxy> = Vec::new();
+    for _ in 0..10 {
+        let global = next_global(&mut registry).await;
+        nodes.push(registry.bind(&global).await.unwrap());
+    }
+    tokio::time::timeout(
+        TIMEOUT,
+        nodes[0].set_param(param::PARAM_PROPS, 0, Value::None),
+    )
+    .await
+    .expect("set_param hung")
+    .unwrap();
+}
@@ -0,0 +1,88 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    os::{
+        fd::{AsFd, OwnedFd},
+        unix::{fs::MetadataExt, net::UnixStream},
+    },
+};
+
+use pipewire_native_protocol::{
+    mock::{global, next_global, MockServer},
+    proxy::Proxy,
+    security_context::{Create, SecurityContextProxy},
+};
+use spa::value::Fd;
+
+fn inode(fd: impl Into<OwnedFd>) -> u64 {
+    File::from(fd.into()).metadata().unwrap().ino()
+}
+
+#[tokio::test]
+async fn create_passes_the_fds() {
+    let (mut server, mut core, _client) =
+        MockServer::connect(vec![global(3, "PipeWire:Interface:SecurityContext", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let context: SecurityContextProxy = registry.bind(&global).await.unwrap();
+
+    let (listen, _) = UnixStream::pair().unwrap();
+    let (close, _) = UnixStream::pair().unwrap();
+    let props = HashMap::from([("pipewire.sec.engine".to_string(), "flatpak".to_string())]);
+    context
+        .create(listen.as_fd(), close.as_fd(), props)
+        .await
+        .unwrap();
+
+    let method = loop {
+        let method = server.next_method().await.unwrap();
+        if method.is::<Create>(context.id()) {
+            break method;
+        }
+    };
+    let create: Create = method.decode().unwrap();
+    assert_eq!((create.listen_fd, create.close_fd), (Fd(0), Fd(1)));
+    assert_eq!(create.props["pipewire.sec.engine"], "flatpak");
+
+    let mut fds = method.fds.into_iter();
+    assert_eq!(inode(fds.next().unwrap()), inode(listen));
+    assert_eq!(inode(fds.next().unwrap()), inode(close));
+    assert!(fds.next().is_none());
+}
+
+#[tokio::test]
+async fn create_sends_the_properties() {
+    let (mut server, mut core, _client) =
+        MockServer::connect(vec![global(3, "PipeWire:Interface:SecurityContext", &[])])
+            .await
+            .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let context: SecurityContextProxy = registry.bind(&global).await.unwrap();
+
+    let (listen, _) = UnixStream::pair().unwrap();
+    let (close, _) = UnixStream::pair().unwrap();
+    let props = HashMap::from([
+        ("pipewire.sec.engine".to_string(), "flatpak".to_string()),
+        ("pipewire.access".to_string(), "restricted".to_string()),
+        (
+            "pipewire.sec.app-id".to_string(),
+            "org.example.App".to_string(),
+        ),
+    ]);
+    context
+        .create(listen.as_fd(), close.as_fd(), props.clone())
+        .await
+        .unwrap();
+    let create: Create = server.wait_for_method(context.id()).await;
+    assert_eq!(create.props, props);
+
+    context
+        .create(listen.as_fd(), close.as_fd(), HashMap::new())
+        .await
+        .unwrap();
+    let create: Create = server.wait_for_method(context.id()).await;
+    assert!(create.props.is_empty());
+}
@@ -0,0 +1,57 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use pipewire_native_protocol::{
+    mock::{global, next_global, MockServer},
+    node::NodeProxy,
+    param, PipewireConnection,
+};
+use spa::value::Value;
+
+// A tap output the test can read back
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn tap_names_the_messages_and_dumps_their_pods() {
+    let buffer = SharedBuffer::default();
+    let (_server, stream) =
+        MockServer::new(vec![global(30, "PipeWire:Interface:Node", &[])]).unwrap();
+    let (mut core, _client) = PipewireConnection::builder()
+        .message_tap(buffer.clone())
+        .connect_stream(stream)
+        .await
+        .unwrap();
+    let mut registry = core.get_registry().await.unwrap();
+    let global = next_global(&mut registry).await;
+    let node: NodeProxy = registry.bind(&global).await.unwrap();
+    node.set_param(param::PARAM_PROPS, 0, Value::Int(1234))
+        .await
+        .unwrap();
+    core.roundtrip().await.unwrap();
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let set_param = output
+        .split(">>>>>>>>> ")
+        .find(|message| message.starts_with("send Node.SetParam "))
+        .expect("No Node.SetParam in the tap output");
+    assert!(set_param.contains(&format!(
+        "  Struct\n    Id {}\n    Int 0\n    Int 1234\n",
+        param::PARAM_PROPS.0
+    )));
+    assert!(output.contains("<<<<<<<<< recv Registry.Global id:"));
+    assert!(output.contains(">>>>>>>>> send Core.Hello id:0 op:1 "));
+}
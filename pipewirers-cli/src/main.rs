@@ -1,9 +1,8 @@
-use std::{env, io::Write};
+use std::io::Write;
 
 use clap::Parser;
 use clap::Subcommand;
 use pipewire_native_protocol::proxy::Proxy;
-use pipewire_native_protocol::registry::RegistryEvent;
 use pipewire_native_protocol::PipewireConnection;
 
 #[derive(Debug, Parser)]
@@ -22,17 +21,7 @@ enum Commands {
 
 #[tokio::main]
 async fn main() -> tokio::io::Result<()> {
-    let mut address = Option::None;
-    if let Ok(pipewire_runtime_dir) = env::var("PIPEWIRE_RUNTIME_DIR") {
-        address = Some(pipewire_runtime_dir + "pipewire-0");
-    } else if let Ok(xdg_runtime_dir) = env::var("XDG_RUNTIME_DIR") {
-        address = Some(xdg_runtime_dir + "pipewire-0");
-    } else if let Ok(userprofile) = env::var("USERPROFILE") {
-        address = Some(userprofile + "pipewire-0");
-    }
-    let address = address.expect("Could not find pipewire socket");
-    let stream = tokio::net::UnixStream::connect(address).await?;
-    let  (mut core_proxy, mut client_proxy) = PipewireConnection::connect(stream).await?;
+    let (mut core_proxy, _client_proxy) = PipewireConnection::connect_default().await?;
 
     let mut registry = core_proxy.get_registry().await?;
 
@@ -56,14 +45,14 @@ async fn main() -> tokio::io::Result<()> {
                                 return Ok(());
                             }
                             Commands::Ls => {
-                                registry.sync().await?;
-                                while let Some(event) = registry.recv().await {
-                                    // TODO: Store the globals we receive, so that next time around we just look at what came untill the next done event
-                                    match event {
-                                        RegistryEvent::Done(done) => break,
-                                        _ => {println!("{}", event);}
-                                    }
-                                }
+                                let sync = registry.sync().await?;
+                                // TODO: Store the globals we receive, so that next time around we just look at what came untill the next done event
+                                registry
+                                    .receive_until(sync, |event| {
+                                        println!("{}", event);
+                                        None
+                                    })
+                                    .await?;
                             },
                         }
                     } else {
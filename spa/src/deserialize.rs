@@ -1988,3 +1988,22 @@ impl<'de> PodDeserialize<'de> for std::collections::HashMap<i32, i32> {
         deserializer.deserialize_struct(HashMapVisitor)
     }
 }
+
+// Deserialize a `None` pod as `None`, any other pod is deserialized as `Some(P)`.
+// This is how nullable values, like optional strings, are sent by PipeWire.
+impl<'de, P: PodDeserialize<'de>> PodDeserialize<'de> for Option<P> {
+    fn deserialize(
+        deserializer: PodDeserializer<'de>,
+    ) -> Result<(Self, DeserializeSuccess<'de>), DeserializeError<&'de [u8]>>
+    where
+        Self: Sized,
+    {
+        if deserializer.peek(PodDeserializer::type_())? == spa_pod_types::NONE {
+            deserializer
+                .deserialize_none(NoneVisitor)
+                .map(|((), success)| (None, success))
+        } else {
+            P::deserialize(deserializer).map(|(value, success)| (Some(value), success))
+        }
+    }
+}
@@ -771,6 +771,19 @@ impl PodSerialize for std::collections::HashMap<i32, i32> {
     }
 }
 
+/// Serialize `None` as a `None` pod and `Some` as the contained value
+impl<P: PodSerialize> PodSerialize for Option<P> {
+    fn serialize<O: Write + Seek>(
+        &self,
+        serializer: PodSerializer<O>,
+    ) -> Result<SerializeSuccess<O>, GenError> {
+        match self {
+            Some(value) => value.serialize(serializer),
+            None => serializer.serialized_fixed_sized_pod(&()),
+        }
+    }
+}
+
 impl<T: CanonicalFixedSizedPod + FixedSizedPod> PodSerialize for std::vec::Vec<T> {
     fn serialize<O: Write + Seek>(
         &self,
@@ -184,15 +184,19 @@ pub struct Fd(pub i64);
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(C)]
 pub struct Rectangle {
-    width: u32,
-    height: u32,
+    /// the width.
+    pub width: u32,
+    /// the height.
+    pub height: u32,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[repr(C)]
 pub struct Fraction {
-    num: u32,
-    denom: u32,
+    /// the numerator.
+    pub num: u32,
+    /// the denominator.
+    pub denom: u32,
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
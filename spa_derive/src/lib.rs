@@ -3,13 +3,16 @@ use quote::{quote, quote_spanned};
 use syn::parse::Parser;
 use syn::spanned::Spanned;
 use syn::{
-    parse, parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Fields, GenericParam, Generics, Index, ItemStruct, LitInt, Token
+    parse, parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Field, Fields, GenericParam, Generics, Ident, Index, ItemStruct, LitInt, Token
 };
 
-#[proc_macro_derive(PodSerialize)]
+#[proc_macro_derive(PodSerialize, attributes(pod))]
 pub fn derive_podserialize(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // Parse the input tokens into a syntax tree.
     let input = parse_macro_input!(input as DeriveInput);
+    if let Err(e) = check_pod_attributes(&input.data) {
+        return e.to_compile_error().into();
+    }
 
     // Used in the quasi-quotation below as `#name`.
     let name = input.ident;
@@ -39,10 +42,13 @@ pub fn derive_podserialize(input: proc_macro::TokenStream) -> proc_macro::TokenS
     proc_macro::TokenStream::from(expanded)
 }
 
-#[proc_macro_derive(PodDeserialize)]
+#[proc_macro_derive(PodDeserialize, attributes(pod))]
 pub fn derive_poddeserialize(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // Parse the input tokens into a syntax tree.
     let input = parse_macro_input!(input as DeriveInput);
+    if let Err(e) = check_pod_attributes(&input.data) {
+        return e.to_compile_error().into();
+    }
 
     // Used in the quasi-quotation below as `#name`.
     let name = input.ident;
@@ -123,6 +129,41 @@ fn add_trait_bounds(mut generics: Generics) -> Generics {
     generics
 }
 
+// Fields marked with #[pod(skip)] are not part of the pod, they are left out when serializing
+// and set to their default value when deserializing
+fn is_skipped(field: &Field) -> parse::Result<bool> {
+    let mut skipped = false;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("pod")) {
+        for argument in pod_arguments(attr)? {
+            if argument != "skip" {
+                return Err(parse::Error::new(
+                    argument.span(),
+                    format!("unknown pod argument `{}`, expected `skip`", argument),
+                ));
+            }
+            skipped = true;
+        }
+    }
+    Ok(skipped)
+}
+
+// Fail on #[pod(...)] attributes with arguments we do not know, instead of ignoring them
+fn check_pod_attributes(data: &Data) -> parse::Result<()> {
+    if let Data::Struct(ref data) = *data {
+        for field in data.fields.iter() {
+            is_skipped(field)?;
+        }
+    }
+    Ok(())
+}
+
+// The comma separated arguments of a #[pod(...)] attribute
+fn pod_arguments(attr: &Attribute) -> parse::Result<Vec<Ident>> {
+    let list = attr.meta.require_list()?;
+    let parser = syn::punctuated::Punctuated::<Ident, Token![,]>::parse_terminated;
+    Ok(parser.parse2(list.tokens.clone())?.into_iter().collect())
+}
+
 // Generate an expression to serialize each field of a struct
 fn field_serialize(data: &Data) -> TokenStream {
     match *data {
@@ -137,6 +178,9 @@ fn field_serialize(data: &Data) -> TokenStream {
                     // but using fully qualified function call syntax.
                     let serialize_fields = fields.named.iter().map(|f| {
                         let name = &f.ident;
+                        if is_skipped(f).unwrap_or(false) {
+                            return quote! {};
+                        }
                         quote_spanned! {f.span()=>
                             struct_serializer.serialize_field(&self.#name)?;
                         }
@@ -186,6 +230,11 @@ fn field_deserialize(data: &Data) -> TokenStream {
                     // but using fully qualified function call syntax.
                     let serialize_fields = fields.named.iter().map(|f| {
                         let name = &f.ident;
+                        if is_skipped(f).unwrap_or(false) {
+                            return quote_spanned! {f.span()=>
+                                #name: Default::default(),
+                            };
+                        }
                         quote_spanned! {f.span()=>
                             #name: struct_deserializer
                                 .deserialize_field()?